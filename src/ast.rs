@@ -1,100 +1,232 @@
+/// Byte offsets into the source file that a node was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// 1-based line and column of the start of the span in `src`.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let before = &src[..self.start.min(src.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
 pub struct Program(pub Vec<TopLevelStmt>);
 
 pub enum TopLevelStmt {
     ClassDef(ClassDef),
+    InterfaceDef(InterfaceDef),
     FunctionDef(FunctionDef),
     ConstDef(GlobalConstDef),
     ExternDef(ExternDef),
 }
 
+#[derive(Clone)]
 pub struct ClassDef {
     pub name: String,
-    pub fields: Vec<(String, Type)>,
+    /// Names that stand for types in the body, given for each instance
+    pub type_params: Vec<String>,
+    /// The base class and interfaces, which can only be told apart once every
+    /// name is known
+    pub parents: Vec<String>,
+    pub fields: Vec<FieldDef>,
+    pub methods: Vec<MethodDef>,
+    pub span: Span,
+}
+
+/// Members without `pub` can only be used in the methods of their class.
+#[derive(Clone)]
+pub struct FieldDef {
+    pub name: String,
+    pub ty: Type,
+    pub is_pub: bool,
+    pub span: Span,
+}
+
+/// A function in a class body. Unless it is static it takes `self` first.
+#[derive(Clone)]
+pub struct MethodDef {
+    pub is_pub: bool,
+    pub is_static: bool,
+    /// Introduces a slot in the class's vtable
+    pub is_virtual: bool,
+    /// Replaces an inherited virtual method in its slot
+    pub is_override: bool,
+    pub func: FunctionDef,
+}
+
+pub struct InterfaceDef {
+    pub name: String,
+    pub methods: Vec<MethodDecl>,
+    pub span: Span,
+}
+
+/// A method signature without a body. The `self` parameter is implied.
+pub struct MethodDecl {
+    pub name: String,
+    pub params: Vec<TypeBinding>,
+    pub return_type: Option<Type>,
+    pub span: Span,
 }
 
+#[derive(Clone)]
 pub struct FunctionDef {
     pub name: String,
+    pub type_params: Vec<String>,
     pub params: Vec<TypeBinding>,
     pub return_type: Option<Type>,
     pub body: BlockStmt,
+    pub span: Span,
 }
 
 pub struct ExternDef {
     pub name: String,
     pub params: Vec<TypeBinding>,
+    /// Declared with `...` after the parameters
+    pub is_variadic: bool,
     pub return_type: Option<Type>,
+    pub span: Span,
 }
 
 pub struct GlobalConstDef {
     pub binding: TypeBinding,
-    pub value: Literal,
+    pub value: Expr,
+    pub span: Span,
 }
 
 // STATEMENTS
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Stmt {
     ExprStmt(Expr),
     BlockStmt(BlockStmt),
     IfStmt(IfStmt),
     WhileStmt(WhileStmt),
+    ForStmt(ForStmt),
     ConstDef(ConstDef),
     VarDef(VarDef),
-    ReturnStmt(Expr),
+    ReturnStmt(ReturnStmt),
+    BreakStmt(LoopJump),
+    ContinueStmt(LoopJump),
 }
 
-#[derive(Debug)]
-pub struct BlockStmt(pub Vec<Stmt>);
+#[derive(Debug, Clone)]
+pub struct BlockStmt {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IfStmt {
     pub condition: Expr,
     pub body: BlockStmt,
     pub else_stmt: Option<IfOrElse>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IfOrElse {
     If(Box<IfStmt>),
     Else(BlockStmt),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WhileStmt {
+    pub label: Option<String>,
     pub condition: Expr,
     pub body: BlockStmt,
+    pub span: Span,
+}
+
+/// `for name in iter { body }`
+#[derive(Debug, Clone)]
+pub struct ForStmt {
+    pub label: Option<String>,
+    pub name: String,
+    pub iter: ForIter,
+    pub body: BlockStmt,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ForIter {
+    /// `start..end`, which stops before `end`
+    Range(Expr, Expr),
+    /// Each item of an array
+    Array(Expr),
+}
+
+/// `break` or `continue`, which applies to the innermost loop unless labelled
+#[derive(Debug, Clone)]
+pub struct LoopJump {
+    pub label: Option<String>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstDef {
     pub binding: TypeBinding,
     pub value: Expr,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VarDef {
     pub binding: TypeBinding,
     pub value: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReturnStmt {
+    pub value: Expr,
+    pub span: Span,
 }
 
 // EXPRESSIONS
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub val: ExprInner,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(val: ExprInner, span: Span) -> Self {
+        Self { val, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprInner {
     Class(ClassExpr),
     FunctionCall(FunctionCall),
     IndexExpr(Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, String),
     Binary(Box<Expr>, BinOp, Box<Expr>),
+    /// `lhs op= rhs`
+    CompoundAssign(Box<Expr>, BinOp, Box<Expr>),
     Unary(UnaryOp, Box<Expr>),
     Array(Vec<Expr>, usize),
     Cast(Box<Expr>, Type),
     Literal(Literal),
     Ident(String),
+    /// A generic function or class with explicit type arguments, as in `max::<int>`
+    Instance(String, Vec<Type>),
 }
 
 #[derive(Debug, Clone)]
 pub struct ClassExpr {
     pub class: String,
+    pub type_args: Vec<Type>,
     pub fields: Vec<(String, Box<Expr>)>,
 }
 
@@ -118,6 +250,9 @@ pub enum BinOp {
     Minus,
     Times,
     Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
     LogicalAnd,
     LogicalOr,
     Equals,
@@ -133,13 +268,17 @@ pub enum BinOp {
 pub struct TypeBinding {
     pub name: String,
     pub ty: Type,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub enum Type {
     Class(String),
+    /// A generic class with type arguments, as in `Vec<int>`
+    Instance(String, Vec<Type>),
     Array(Box<Type>, usize),
     Ref(Box<Type>),
+    Function(Vec<Type>, Option<Box<Type>>),
     Int,
     Int8,
     Int16,
@@ -150,6 +289,8 @@ pub enum Type {
     UInt16,
     UInt32,
     UInt64,
+    Float,
+    Double,
     Char,
     Str,
     CStr,
@@ -158,7 +299,7 @@ pub enum Type {
 
 #[derive(Debug, Clone)]
 pub enum Literal {
-    Int(i32),
+    Int(i128),
     UInt(u32),
     Int8(i8),
     Int16(i16),
@@ -168,6 +309,8 @@ pub enum Literal {
     UInt16(u16),
     UInt32(u32),
     UInt64(u64),
+    Double(f64),
+    Char(u8),
     Str(String),
     CStr(String),
     Bool(bool),
@@ -1,59 +1,113 @@
-use super::symbol::{ScopedSymbolTable, Symbol};
+use super::symbol::{Cleanup, ScopedSymbolTable, Symbol};
+use super::target::TargetMachine;
 use crate::c_str;
 use crate::codegen::error::CodegenError;
 use crate::type_checker::typed_ast;
-use crate::type_checker::typed_ast::ClassDef;
+use crate::type_checker::typed_ast::{ClassDef, InterfaceDef};
 
 use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::path::Path;
 
 use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
 use llvm_sys::core::*;
-use llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
+use llvm_sys::prelude::{LLVMBasicBlockRef, LLVMTypeRef, LLVMValueRef};
+use llvm_sys::target_machine::LLVMCodeGenFileType;
 use llvm_sys::{LLVMBuilder, LLVMContext, LLVMModule};
 
+/// Where `break` and `continue` jump to in a loop being generated.
+pub struct LoopTarget {
+    pub label: Option<String>,
+    pub break_block: LLVMBasicBlockRef,
+    pub continue_block: LLVMBasicBlockRef,
+    /// Scopes from this depth are left by jumping out of the body
+    pub depth: usize,
+}
+
 pub struct CompilerContext {
-    pub symbols: ScopedSymbolTable<Symbol>,
+    pub symbols: ScopedSymbolTable<Symbol, Cleanup>,
+    /// Objects made by the statement being generated that nothing owns
+    temporaries: Vec<Cleanup>,
     classes: HashMap<String, (LLVMTypeRef, ClassDef)>,
+    vtables: HashMap<String, LLVMValueRef>,
+    interfaces: HashMap<String, InterfaceDef>,
+    /// Tables for interface pointers, by class and interface
+    interface_tables: HashMap<(String, String), LLVMValueRef>,
     func_stack: Vec<LLVMValueRef>,
+    loops: Vec<LoopTarget>,
     context: *mut LLVMContext,
     module: *mut LLVMModule,
     builder: *mut LLVMBuilder,
+    target: TargetMachine,
 }
 
 impl CompilerContext {
-    pub fn new() -> Self {
+    pub fn new(target: TargetMachine) -> Self {
         unsafe {
             let context = LLVMContextCreate();
             let module = LLVMModuleCreateWithName(c_str!("main"));
             let builder = LLVMCreateBuilderInContext(context);
-            LLVMSetTarget(module, c_str!("x86_64-pc-linux-gnu"));
+            target.configure_module(module);
 
             Self {
                 symbols: ScopedSymbolTable::new(),
+                temporaries: vec![],
                 classes: HashMap::new(),
+                vtables: HashMap::new(),
+                interfaces: HashMap::new(),
+                interface_tables: HashMap::new(),
                 func_stack: vec![],
+                loops: vec![],
                 context,
                 module,
                 builder,
+                target,
             }
         }
     }
 
-    pub unsafe fn compile_to_file(
-        &mut self,
-        ast: typed_ast::Program,
-        output_file: &str,
-    ) -> Result<(), CodegenError> {
-        ast.codegen(self, self.context, self.module, self.builder)?;
+    pub unsafe fn compile(&mut self, ast: typed_ast::Program) -> Result<(), CodegenError> {
+        ast.codegen(self, self.context, self.module, self.builder)
+    }
 
-        use std::ffi::CString;
-        let name = CString::new(output_file).unwrap();
-        LLVMPrintModuleToFile(self.module, c_str!("main.ll"), std::ptr::null_mut());
-        LLVMWriteBitcodeToFile(self.module, name.as_ptr() as *const i8);
+    /// Write the module as textual LLVM IR.
+    pub unsafe fn write_llvm_ir(&self, path: &Path) -> Result<(), CodegenError> {
+        let c_path = path_to_c_string(path)?;
+        let mut message = std::ptr::null_mut();
+        if LLVMPrintModuleToFile(self.module, c_path.as_ptr(), &mut message) != 0 {
+            let err = CStr::from_ptr(message).to_string_lossy().into_owned();
+            LLVMDisposeMessage(message);
+            return Err(CodegenError::WriteFailed(err));
+        }
 
         Ok(())
     }
 
+    /// Write the module as LLVM bitcode.
+    pub unsafe fn write_bitcode(&self, path: &Path) -> Result<(), CodegenError> {
+        let c_path = path_to_c_string(path)?;
+        if LLVMWriteBitcodeToFile(self.module, c_path.as_ptr()) != 0 {
+            return Err(CodegenError::WriteFailed(format!(
+                "could not write bitcode to {}",
+                path.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Write the module as native assembly for the target machine.
+    pub unsafe fn write_assembly(&self, path: &Path) -> Result<(), CodegenError> {
+        self.target
+            .emit_to_file(self.module, path, LLVMCodeGenFileType::LLVMAssemblyFile)
+    }
+
+    /// Write the module as a native object file for the target machine.
+    pub unsafe fn write_object(&self, path: &Path) -> Result<(), CodegenError> {
+        self.target
+            .emit_to_file(self.module, path, LLVMCodeGenFileType::LLVMObjectFile)
+    }
+
     pub fn add_func(&mut self, func: LLVMValueRef) {
         self.func_stack.push(func);
     }
@@ -66,6 +120,23 @@ impl CompilerContext {
         *self.func_stack.last().unwrap()
     }
 
+    pub fn push_loop(&mut self, target: LoopTarget) {
+        self.loops.push(target);
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loops.pop();
+    }
+
+    /// The innermost loop with `label`, or the innermost loop if there is none.
+    pub fn loop_target(&self, label: Option<&str>) -> &LoopTarget {
+        self.loops
+            .iter()
+            .rev()
+            .find(|target| label.is_none() || target.label.as_deref() == label)
+            .expect("type checker only allows jumps inside a loop")
+    }
+
     pub fn add_class(&mut self, ty: LLVMTypeRef, class: ClassDef) {
         self.classes.insert(class.name.clone(), (ty, class));
     }
@@ -73,6 +144,54 @@ impl CompilerContext {
     pub fn class(&self, name: &str) -> Option<&(LLVMTypeRef, ClassDef)> {
         self.classes.get(name)
     }
+
+    /// Record the pointer that objects of the class store as their vtable.
+    pub fn add_vtable(&mut self, class: String, vtable: LLVMValueRef) {
+        self.vtables.insert(class, vtable);
+    }
+
+    pub fn vtable(&self, class: &str) -> Option<LLVMValueRef> {
+        self.vtables.get(class).copied()
+    }
+
+    /// Whether values of type `ty` have a `deinit` to run, directly or in a field.
+    pub fn needs_drop(&self, ty: &typed_ast::Type) -> bool {
+        ty.needs_drop(&|name| self.class(name).map(|(_, def)| def))
+    }
+
+    /// Record a temporary object, to be destroyed at the end of its statement.
+    pub fn add_temporary(&mut self, temporary: Cleanup) {
+        self.temporaries.push(temporary);
+    }
+
+    /// How many temporaries there are, to later take the ones made after now.
+    pub fn temporary_mark(&self) -> usize {
+        self.temporaries.len()
+    }
+
+    /// Remove the temporaries made since `mark`.
+    pub fn take_temporaries(&mut self, mark: usize) -> Vec<Cleanup> {
+        self.temporaries.split_off(mark)
+    }
+
+    pub fn add_interface(&mut self, interface: InterfaceDef) {
+        self.interfaces.insert(interface.name.clone(), interface);
+    }
+
+    pub fn interface(&self, name: &str) -> Option<&InterfaceDef> {
+        self.interfaces.get(name)
+    }
+
+    /// Record the table that pointers to `class` carry as an `interface`.
+    pub fn add_interface_table(&mut self, class: String, interface: String, table: LLVMValueRef) {
+        self.interface_tables.insert((class, interface), table);
+    }
+
+    pub fn interface_table(&self, class: &str, interface: &str) -> Option<LLVMValueRef> {
+        self.interface_tables
+            .get(&(class.to_owned(), interface.to_owned()))
+            .copied()
+    }
 }
 
 impl Drop for CompilerContext {
@@ -84,3 +203,8 @@ impl Drop for CompilerContext {
         }
     }
 }
+
+fn path_to_c_string(path: &Path) -> Result<CString, CodegenError> {
+    CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|_| CodegenError::WriteFailed(format!("invalid path: {}", path.display())))
+}
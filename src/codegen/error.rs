@@ -1,14 +1,34 @@
+use crate::ast::Span;
+
 use std::fmt;
 
 #[derive(Debug)]
 pub enum CodegenError {
-    BadPtrGen,
+    BadPtrGen(Span),
+    WriteFailed(String),
+    BadTarget(String),
+    LinkFailed(String),
+}
+
+impl CodegenError {
+    /// Location in the source that caused the error, if there is one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CodegenError::BadPtrGen(span) => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for CodegenError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CodegenError::BadPtrGen => write!(f, "Bad ptr gen"),
+            CodegenError::BadPtrGen(_) => {
+                write!(f, "Expression does not refer to a memory location")
+            }
+            CodegenError::WriteFailed(msg) => write!(f, "Could not write output: {}", msg),
+            CodegenError::BadTarget(msg) => write!(f, "Unsupported target: {}", msg),
+            CodegenError::LinkFailed(msg) => write!(f, "Linking failed: {}", msg),
         }
     }
 }
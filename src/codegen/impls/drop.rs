@@ -0,0 +1,144 @@
+use super::EMPTY_NAME;
+use crate::codegen::context::CompilerContext;
+use crate::codegen::symbol::Cleanup;
+use crate::type_checker::typed_ast;
+
+use std::os::raw::{c_uint, c_ulonglong};
+
+use llvm_sys::core::*;
+use llvm_sys::prelude::LLVMValueRef;
+use llvm_sys::LLVMLinkage;
+
+impl typed_ast::ClassDef {
+    /// Define the function that destroys objects of this class. It runs
+    /// `deinit`, then destroys the fields, then does the same for the base.
+    pub unsafe fn define_drop(
+        &self,
+        ctx: &CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) {
+        let func = drop_function(ctx, context, module, &self.name);
+        let block = LLVMAppendBasicBlockInContext(context, func, EMPTY_NAME);
+        LLVMPositionBuilderAtEnd(builder, block);
+        let object = LLVMGetParam(func, 0);
+
+        if self.own_method(typed_ast::DEINIT).is_some() {
+            let deinit = typed_ast::method_symbol(&self.name, typed_ast::DEINIT);
+            let deinit = ctx.symbols.get_symbol(&deinit).unwrap().value;
+            LLVMBuildCall(builder, deinit, [object].as_mut_ptr(), 1, EMPTY_NAME);
+        }
+
+        let inherited = match &self.base {
+            Some(base) => ctx.class(base).unwrap().1.fields.len(),
+            None => 0,
+        };
+        for field in self.fields[inherited..].iter().rev() {
+            let index = self.field_index(&field.name).unwrap();
+            let ptr = LLVMBuildStructGEP(builder, object, index as c_uint, EMPTY_NAME);
+            build_drop(ctx, context, module, builder, ptr, &field.ty);
+        }
+
+        if let Some(base) = &self.base {
+            let base_ty = typed_ast::Type::Class(base.clone());
+            let base_ptr = LLVMBuildBitCast(
+                builder,
+                object,
+                LLVMPointerType(ctx.class(base).unwrap().0, 0),
+                EMPTY_NAME,
+            );
+            build_drop(ctx, context, module, builder, base_ptr, &base_ty);
+        }
+
+        LLVMBuildRetVoid(builder);
+    }
+}
+
+/// The function that destroys objects of `class`, declared on first use.
+unsafe fn drop_function(
+    ctx: &CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    class: &str,
+) -> LLVMValueRef {
+    use std::ffi::CString;
+    let c_name = CString::new(format!("{}.drop", class)).unwrap();
+    let existing = LLVMGetNamedFunction(module, c_name.as_ptr());
+    if !existing.is_null() {
+        return existing;
+    }
+
+    let mut params = [LLVMPointerType(ctx.class(class).unwrap().0, 0)];
+    let func_ty = LLVMFunctionType(LLVMVoidTypeInContext(context), params.as_mut_ptr(), 1, 0);
+    let func = LLVMAddFunction(module, c_name.as_ptr(), func_ty);
+    LLVMSetLinkage(func, LLVMLinkage::LLVMPrivateLinkage);
+    func
+}
+
+/// Destroy the value of type `ty` stored at `ptr`.
+pub unsafe fn build_drop(
+    ctx: &CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    ptr: LLVMValueRef,
+    ty: &typed_ast::Type,
+) {
+    if !ctx.needs_drop(ty) {
+        return;
+    }
+
+    match ty {
+        typed_ast::Type::Class(name) => {
+            let func = drop_function(ctx, context, module, name);
+            LLVMBuildCall(builder, func, [ptr].as_mut_ptr(), 1, EMPTY_NAME);
+        }
+        // Last element first, like the fields of a class
+        typed_ast::Type::Array(inner, len) => {
+            let i64_type = LLVMInt64TypeInContext(context);
+            let zero = LLVMConstInt(i64_type, 0, 0);
+            for i in (0..*len).rev() {
+                let index = LLVMConstInt(i64_type, i as c_ulonglong, 0);
+                let element =
+                    LLVMBuildInBoundsGEP(builder, ptr, [zero, index].as_mut_ptr(), 2, EMPTY_NAME);
+                build_drop(ctx, context, module, builder, element, inner);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Run `cleanups` in order, unless the current block has already returned.
+pub unsafe fn build_cleanups<'a>(
+    ctx: &CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    cleanups: impl IntoIterator<Item = &'a Cleanup>,
+) {
+    if is_terminated(builder) {
+        return;
+    }
+
+    for cleanup in cleanups {
+        build_drop(ctx, context, module, builder, cleanup.ptr, &cleanup.ty);
+    }
+}
+
+/// Destroy the temporaries made since `mark`, newest first.
+pub unsafe fn build_temporary_cleanups(
+    ctx: &mut CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    mark: usize,
+) {
+    let temporaries = ctx.take_temporaries(mark);
+    build_cleanups(ctx, context, module, builder, temporaries.iter().rev());
+}
+
+/// Whether the block being built already ends in a terminator.
+pub unsafe fn is_terminated(builder: *mut llvm_sys::LLVMBuilder) -> bool {
+    !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(builder)).is_null()
+}
@@ -1,19 +1,39 @@
-use super::EMPTY_NAME;
+use super::drop::{build_drop, build_temporary_cleanups};
+use super::{build_entry_alloca, EMPTY_NAME};
 use crate::codegen::context::CompilerContext;
 use crate::codegen::error::CodegenError;
 use crate::type_checker::typed_ast;
 
-use crate::codegen::symbol::SymbolType;
+use crate::codegen::symbol::{Cleanup, SymbolType};
 
 use std::os::raw::{c_uint, c_ulonglong};
 
 use llvm_sys::core::*;
-use llvm_sys::prelude::LLVMValueRef;
+use llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
+use llvm_sys::target::*;
+use llvm_sys::LLVMIntPredicate;
 
 impl typed_ast::Expr {
     pub unsafe fn codegen(
         &self,
-        ctx: &CompilerContext,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<llvm_sys::prelude::LLVMValueRef, CodegenError> {
+        let value = self.codegen_owned(ctx, context, module, builder)?;
+        if self.is_new_object() && ctx.needs_drop(&self.ty) {
+            self.spill(ctx, builder, value);
+        }
+
+        Ok(value)
+    }
+
+    /// Like `codegen`, but the caller becomes responsible for destroying any
+    /// new object, as variables and return values do.
+    pub unsafe fn codegen_owned(
+        &self,
+        ctx: &mut CompilerContext,
         context: *mut llvm_sys::LLVMContext,
         module: *mut llvm_sys::LLVMModule,
         builder: *mut llvm_sys::LLVMBuilder,
@@ -22,37 +42,67 @@ impl typed_ast::Expr {
 
         let val = match &self.val {
             ExprInner::Class(class_expr) => {
-                let (llvm_ty, def) = ctx.class(&class_expr.class).unwrap();
-                let alloca = LLVMBuildAlloca(builder, *llvm_ty, EMPTY_NAME);
+                let (llvm_ty, def) = ctx.class(&class_expr.class).unwrap().clone();
+                let alloca = build_entry_alloca(builder, llvm_ty);
+
+                if let Some(vtable) = ctx.vtable(&def.name) {
+                    let field = LLVMBuildStructGEP(builder, alloca, 0, EMPTY_NAME);
+                    LLVMBuildStore(builder, vtable, field);
+                }
 
                 // TODO: Get rid of this unwrap
                 for (name, value) in class_expr.fields.iter() {
-                    let i = def.fields.iter().position(|(n, _)| n == name).unwrap();
+                    let i = def.field_index(name).unwrap();
                     let field = LLVMBuildStructGEP(builder, alloca, i as c_uint, EMPTY_NAME);
-                    LLVMBuildStore(
-                        builder,
-                        value.codegen(ctx, context, module, builder)?,
-                        field,
-                    );
+                    // The object takes over the field values
+                    let value = value.codegen_owned(ctx, context, module, builder)?;
+                    LLVMBuildStore(builder, value, field);
                 }
 
                 LLVMBuildLoad(builder, alloca, EMPTY_NAME)
             }
+            ExprInner::Construct(construct) => {
+                let llvm_ty = ctx.class(&construct.class).unwrap().0;
+                let alloca = build_entry_alloca(builder, llvm_ty);
+
+                // Fields `init` leaves alone are zero
+                LLVMBuildStore(builder, LLVMConstNull(llvm_ty), alloca);
+                if let Some(vtable) = ctx.vtable(&construct.class) {
+                    let field = LLVMBuildStructGEP(builder, alloca, 0, EMPTY_NAME);
+                    LLVMBuildStore(builder, vtable, field);
+                }
+
+                let mut args = vec![alloca];
+                for arg in construct.args.iter() {
+                    args.push(arg.codegen(ctx, context, module, builder)?);
+                }
+                let init = typed_ast::method_symbol(&construct.class, typed_ast::INIT);
+                let init = ctx.symbols.get_symbol(&init).unwrap().value;
+                LLVMBuildCall(
+                    builder,
+                    init,
+                    args.as_mut_ptr(),
+                    args.len() as c_uint,
+                    EMPTY_NAME,
+                );
+
+                LLVMBuildLoad(builder, alloca, EMPTY_NAME)
+            }
             ExprInner::Array(array_expr) => {
                 let i64_type = LLVMInt64TypeInContext(context);
                 let ty = self.ty.as_llvm_type(ctx, context);
-                let alloca = LLVMBuildAlloca(builder, ty, EMPTY_NAME);
+                let alloca = build_entry_alloca(builder, ty);
                 let zero = LLVMConstInt(i64_type, 0, 0);
                 // TODO: Can optimize this
                 // Look how clang does it by chaining GEP instructions
                 for (i, item) in array_expr.items.iter().enumerate() {
-                    let value = item.codegen(ctx, context, module, builder)?;
+                    let value = item.codegen_owned(ctx, context, module, builder)?;
                     let index = LLVMConstInt(i64_type, i as c_ulonglong, 0);
                     let array_val = LLVMBuildInBoundsGEP2(
                         builder,
                         ty,
                         alloca,
-                        [index, zero].as_mut_ptr(),
+                        [zero, index].as_mut_ptr(),
                         2,
                         EMPTY_NAME,
                     );
@@ -61,124 +111,176 @@ impl typed_ast::Expr {
                 LLVMBuildLoad(builder, alloca, EMPTY_NAME)
             }
             ExprInner::IndexExpr(index_expr) => {
-                // Use getelementptr instruction
-                let data = index_expr.name.codegen(ctx, context, module, builder)?;
-                let index = index_expr.index.codegen(ctx, context, module, builder)?;
-
-                let ptr = LLVMBuildGEP(builder, data, [index].as_mut_ptr(), 1, EMPTY_NAME);
+                let ptr = index_expr.element_ptr(ctx, context, module, builder)?;
+                LLVMBuildLoad(builder, ptr, EMPTY_NAME)
+            }
+            ExprInner::Member(member) => {
+                let ptr = member.field_ptr(ctx, context, module, builder)?;
                 LLVMBuildLoad(builder, ptr, EMPTY_NAME)
             }
             ExprInner::FunctionCall(call) => {
-                let func = call.name.codegen(ctx, context, module, builder)?;
                 // TODO: Handle error better
-                let mut args: Vec<LLVMValueRef> = call
+                let args: Vec<LLVMValueRef> = call
                     .args
                     .iter()
                     .map(|expr| expr.codegen(ctx, context, module, builder))
                     .collect::<Result<Vec<LLVMValueRef>, CodegenError>>()?;
-
-                LLVMBuildCall(
-                    builder,
-                    func,
-                    args.as_mut_ptr(),
-                    args.len() as c_uint,
-                    EMPTY_NAME,
-                )
+                build_call(ctx, context, module, builder, call, args)?
+            }
+            ExprInner::Binary(binary_expr)
+                if matches!(
+                    binary_expr.op,
+                    typed_ast::BinOp::LogicalAnd | typed_ast::BinOp::LogicalOr
+                ) =>
+            {
+                build_short_circuit(ctx, context, module, builder, binary_expr)?
+            }
+            // Assignment replaces the old value, so it is destroyed first
+            ExprInner::Binary(binary_expr) if binary_expr.op == typed_ast::BinOp::Equals => {
+                let r_val = binary_expr
+                    .rhs
+                    .codegen_owned(ctx, context, module, builder)?;
+                let l_ptr = binary_expr.lhs.codegen_ptr(ctx, context, module, builder)?;
+                build_drop(ctx, context, module, builder, l_ptr, &binary_expr.lhs.ty);
+                LLVMBuildStore(builder, r_val, l_ptr)
+            }
+            ExprInner::FieldInit(binary_expr) => {
+                let r_val = binary_expr
+                    .rhs
+                    .codegen_owned(ctx, context, module, builder)?;
+                let l_ptr = binary_expr.lhs.codegen_ptr(ctx, context, module, builder)?;
+                LLVMBuildStore(builder, r_val, l_ptr)
+            }
+            // Objects run their operator method on the target where it is
+            ExprInner::CompoundAssign(assign)
+                if matches!(assign.lhs.ty, typed_ast::Type::Class(_)) =>
+            {
+                let call = match &assign.rhs.val {
+                    ExprInner::FunctionCall(call) => call,
+                    _ => unreachable!("objects are updated by their operator method"),
+                };
+                let l_ptr = assign.lhs.codegen_ptr(ctx, context, module, builder)?;
+                let mut args = vec![l_ptr];
+                for arg in call.args[1..].iter() {
+                    args.push(arg.codegen(ctx, context, module, builder)?);
+                }
+                let result = build_call(ctx, context, module, builder, call, args)?;
+                build_drop(ctx, context, module, builder, l_ptr, &assign.lhs.ty);
+                LLVMBuildStore(builder, result, l_ptr)
+            }
+            ExprInner::CompoundAssign(assign) => {
+                let r_val = assign.rhs.codegen(ctx, context, module, builder)?;
+                let l_ptr = assign.lhs.codegen_ptr(ctx, context, module, builder)?;
+                let l_val = LLVMBuildLoad(builder, l_ptr, EMPTY_NAME);
+                let result = if assign.lhs.ty.is_float() {
+                    build_float_binary(builder, assign.op, l_val, r_val)
+                } else {
+                    build_int_arithmetic(
+                        context,
+                        module,
+                        builder,
+                        assign.op,
+                        l_val,
+                        r_val,
+                        assign.lhs.ty.is_unsigned(),
+                    )
+                };
+                LLVMBuildStore(builder, result, l_ptr)
+            }
+            ExprInner::Binary(binary_expr) if binary_expr.lhs.ty.is_float() => {
+                let l_val = binary_expr.lhs.codegen(ctx, context, module, builder)?;
+                let r_val = binary_expr.rhs.codegen(ctx, context, module, builder)?;
+                build_float_binary(builder, binary_expr.op, l_val, r_val)
             }
             ExprInner::Binary(binary_expr) => {
                 use typed_ast::BinOp;
 
                 let l_val = binary_expr.lhs.codegen(ctx, context, module, builder)?;
                 let r_val = binary_expr.rhs.codegen(ctx, context, module, builder)?;
+                let unsigned = binary_expr.lhs.ty.is_unsigned();
 
-                // TODO: This assumes l and r are both signed ints
-                // should have handling for unsigned and floats as well
-
-                // TODO: LogicalAnd should be typechecked to ensure LHS and RHS
-                // are both i1 types. If they are then it works as intended. Otherwise
-                // it can give values other than 0 or 1.
                 match binary_expr.op {
-                    BinOp::Plus => LLVMBuildAdd(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::Minus => LLVMBuildSub(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::Times => LLVMBuildMul(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::Divide => LLVMBuildSDiv(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::LogicalAnd => LLVMBuildAnd(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::LogicalOr => LLVMBuildOr(builder, l_val, r_val, EMPTY_NAME),
-                    BinOp::Equals => {
-                        // TODO: Assert LHS is an ident or indexexpr
-                        // Build store
-                        // TODO: Somehow don't fully codegen l val because we want the ptr to it
-                        // We know that l val has to be bound to some variable for this to work
-                        let l_ptr = binary_expr.lhs.codegen_ptr(ctx, context, module, builder)?;
-                        LLVMBuildStore(builder, r_val, l_ptr)
+                    op @ (BinOp::Plus
+                    | BinOp::Minus
+                    | BinOp::Times
+                    | BinOp::Divide
+                    | BinOp::Remainder) => {
+                        build_int_arithmetic(context, module, builder, op, l_val, r_val, unsigned)
+                    }
+                    BinOp::ShiftLeft | BinOp::ShiftRight => {
+                        let amount = shift_amount(builder, binary_expr, l_val, r_val);
+                        match binary_expr.op {
+                            BinOp::ShiftLeft => LLVMBuildShl(builder, l_val, amount, EMPTY_NAME),
+                            _ if unsigned => LLVMBuildLShr(builder, l_val, amount, EMPTY_NAME),
+                            _ => LLVMBuildAShr(builder, l_val, amount, EMPTY_NAME),
+                        }
+                    }
+                    op => {
+                        let predicate = int_predicate(op, unsigned);
+                        LLVMBuildICmp(builder, predicate, l_val, r_val, EMPTY_NAME)
                     }
-                    BinOp::Eq => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntEQ,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
-                    BinOp::Ne => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntNE,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
-                    BinOp::Gt => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntSGT,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
-                    BinOp::Gte => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntSGE,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
-                    BinOp::Lt => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntSLT,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
-                    BinOp::Lte => LLVMBuildICmp(
-                        builder,
-                        llvm_sys::LLVMIntPredicate::LLVMIntSLE,
-                        l_val,
-                        r_val,
-                        EMPTY_NAME,
-                    ),
                 }
             }
+            // Temporaries only get referenced to pass them as `self`
+            ExprInner::Unary(unary_expr) if unary_expr.op == typed_ast::UnaryOp::Reference => {
+                unary_expr
+                    .data
+                    .codegen_ptr_or_spill(ctx, context, module, builder)?
+            }
             ExprInner::Unary(unary_expr) => {
                 let data_val = unary_expr.data.codegen(ctx, context, module, builder)?;
 
                 use typed_ast::UnaryOp;
                 match unary_expr.op {
-                    UnaryOp::Reference => {
-                        unary_expr.data.codegen_ptr(ctx, context, module, builder)?
-                    }
+                    UnaryOp::Reference => unreachable!(),
                     UnaryOp::Deref => LLVMBuildLoad(builder, data_val, EMPTY_NAME),
+                    UnaryOp::Minus if unary_expr.data.ty.is_float() => {
+                        LLVMBuildFNeg(builder, data_val, EMPTY_NAME)
+                    }
                     UnaryOp::Minus => LLVMBuildNeg(builder, data_val, EMPTY_NAME),
                     UnaryOp::Not => LLVMBuildNot(builder, data_val, EMPTY_NAME),
                 }
             }
-            ExprInner::Cast(cast_expr) => LLVMBuildCast(
-                builder,
-                llvm_sys::LLVMOpcode::LLVMSExt,
-                cast_expr.original.codegen(ctx, context, module, builder)?,
-                cast_expr.to_type.as_llvm_type(ctx, context),
-                EMPTY_NAME,
-            ),
+            ExprInner::Cast(cast_expr) => {
+                let original = cast_expr.original.codegen(ctx, context, module, builder)?;
+                match (&cast_expr.original.ty, &cast_expr.to_type) {
+                    (typed_ast::Type::Str, typed_ast::Type::CStr) => {
+                        LLVMBuildExtractValue(builder, original, 0, EMPTY_NAME)
+                    }
+                    (typed_ast::Type::Ref(from), typed_ast::Type::Ref(to))
+                        if self.ty.is_interface_ref() && from != to =>
+                    {
+                        let table = match (&**from, &**to) {
+                            (
+                                typed_ast::Type::Class(class),
+                                typed_ast::Type::Interface(interface),
+                            ) => ctx.interface_table(class, interface).unwrap(),
+                            _ => unreachable!("only class pointers convert to interfaces"),
+                        };
+                        let i8_ptr = LLVMPointerType(LLVMInt8TypeInContext(context), 0);
+                        let object = LLVMBuildBitCast(builder, original, i8_ptr, EMPTY_NAME);
+                        let pair = LLVMGetUndef(self.ty.as_llvm_type(ctx, context));
+                        let pair = LLVMBuildInsertValue(builder, pair, object, 0, EMPTY_NAME);
+                        LLVMBuildInsertValue(builder, pair, table, 1, EMPTY_NAME)
+                    }
+                    (typed_ast::Type::CStr, typed_ast::Type::Str) => {
+                        let len = build_strlen(context, module, builder, original);
+                        let string = LLVMGetUndef(self.ty.as_llvm_type(ctx, context));
+                        let string = LLVMBuildInsertValue(builder, string, original, 0, EMPTY_NAME);
+                        LLVMBuildInsertValue(builder, string, len, 1, EMPTY_NAME)
+                    }
+                    (from, to) => build_cast(
+                        builder,
+                        original,
+                        from,
+                        to,
+                        self.ty.as_llvm_type(ctx, context),
+                    ),
+                }
+            }
             ExprInner::Literal(lit) => lit.codegen(ctx, context, module, builder),
             ExprInner::Ident(ident) => {
-                let symbol = ctx.symbols.get_symbol(&ident).unwrap();
+                let symbol = ctx.symbols.get_symbol(ident).unwrap();
 
                 match symbol.ty {
                     SymbolType::Const => LLVMBuildLoad(builder, symbol.value, EMPTY_NAME),
@@ -186,6 +288,15 @@ impl typed_ast::Expr {
                     SymbolType::Func => symbol.value,
                 }
             }
+            ExprInner::Len(data) => match &data.ty {
+                typed_ast::Type::Array(_, len) => {
+                    LLVMConstInt(LLVMInt32TypeInContext(context), *len as c_ulonglong, 0)
+                }
+                _ => {
+                    let string = data.codegen(ctx, context, module, builder)?;
+                    LLVMBuildExtractValue(builder, string, 1, EMPTY_NAME)
+                }
+            },
         };
 
         Ok(val)
@@ -193,7 +304,7 @@ impl typed_ast::Expr {
 
     pub unsafe fn codegen_ptr(
         &self,
-        ctx: &CompilerContext,
+        ctx: &mut CompilerContext,
         context: *mut llvm_sys::LLVMContext,
         module: *mut llvm_sys::LLVMModule,
         builder: *mut llvm_sys::LLVMBuilder,
@@ -201,26 +312,460 @@ impl typed_ast::Expr {
         use typed_ast::ExprInner;
         // TODO: Replace panics with nice errors
         if let ExprInner::Ident(ident) = &self.val {
-            let symbol = ctx.symbols.get_symbol(&ident).unwrap();
+            let symbol = ctx.symbols.get_symbol(ident).unwrap();
             Ok(symbol.value)
         } else if let ExprInner::IndexExpr(index_expr) = &self.val {
-            let data = index_expr.name.codegen(ctx, context, module, builder)?;
-            let index = index_expr.index.codegen(ctx, context, module, builder)?;
-            Ok(LLVMBuildGEP(
-                builder,
-                data,
-                [index].as_mut_ptr(),
-                1,
-                EMPTY_NAME,
-            ))
+            index_expr.element_ptr(ctx, context, module, builder)
+        } else if let ExprInner::Member(member) = &self.val {
+            member.field_ptr(ctx, context, module, builder)
         } else if let ExprInner::Unary(unary_expr) = &self.val {
-            if unary_expr.op == typed_ast::UnaryOp::Reference {
-                self.codegen(ctx, context, module, builder)
-            } else {
-                Err(CodegenError::BadPtrGen)
+            match unary_expr.op {
+                typed_ast::UnaryOp::Reference => self.codegen(ctx, context, module, builder),
+                typed_ast::UnaryOp::Deref => unary_expr.data.codegen(ctx, context, module, builder),
+                _ => Err(CodegenError::BadPtrGen(self.span)),
             }
         } else {
-            Err(CodegenError::BadPtrGen)
+            Err(CodegenError::BadPtrGen(self.span))
+        }
+    }
+
+    /// Whether this makes an object that did not exist before.
+    fn is_new_object(&self) -> bool {
+        matches!(
+            self.val,
+            typed_ast::ExprInner::Class(_)
+                | typed_ast::ExprInner::Array(_)
+                | typed_ast::ExprInner::Construct(_)
+                | typed_ast::ExprInner::FunctionCall(_)
+        )
+    }
+
+    /// Like `codegen_ptr`, but values that are not in memory yet are stored
+    /// in a temporary first. Used to get at the insides of arrays and classes.
+    pub unsafe fn codegen_ptr_or_spill(
+        &self,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<LLVMValueRef, CodegenError> {
+        match self.codegen_ptr(ctx, context, module, builder) {
+            Err(CodegenError::BadPtrGen(_)) => {
+                let value = self.codegen_owned(ctx, context, module, builder)?;
+                Ok(self.spill(ctx, builder, value))
+            }
+            result => result,
+        }
+    }
+
+    /// Store `value` in a new temporary. New objects that nothing takes over
+    /// are destroyed after the statement.
+    unsafe fn spill(
+        &self,
+        ctx: &mut CompilerContext,
+        builder: *mut llvm_sys::LLVMBuilder,
+        value: LLVMValueRef,
+    ) -> LLVMValueRef {
+        let alloca = build_entry_alloca(builder, LLVMTypeOf(value));
+        LLVMBuildStore(builder, value, alloca);
+        if self.is_new_object() && ctx.needs_drop(&self.ty) {
+            ctx.add_temporary(Cleanup::new(alloca, self.ty.clone()));
         }
+        alloca
     }
 }
+
+impl typed_ast::IndexExpr {
+    unsafe fn element_ptr(
+        &self,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<LLVMValueRef, CodegenError> {
+        let data = match &self.name.ty {
+            typed_ast::Type::Array(..) => self
+                .name
+                .codegen_ptr_or_spill(ctx, context, module, builder)?,
+            typed_ast::Type::Str => {
+                let string = self.name.codegen(ctx, context, module, builder)?;
+                LLVMBuildExtractValue(builder, string, 0, EMPTY_NAME)
+            }
+            _ => self.name.codegen(ctx, context, module, builder)?,
+        };
+        let index = self.index.codegen(ctx, context, module, builder)?;
+
+        Ok(match &self.name.ty {
+            typed_ast::Type::Array(..) => {
+                let zero = LLVMConstInt(LLVMTypeOf(index), 0, 0);
+                LLVMBuildGEP(builder, data, [zero, index].as_mut_ptr(), 2, EMPTY_NAME)
+            }
+            _ => LLVMBuildGEP(builder, data, [index].as_mut_ptr(), 1, EMPTY_NAME),
+        })
+    }
+}
+
+impl typed_ast::MemberExpr {
+    unsafe fn field_ptr(
+        &self,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<LLVMValueRef, CodegenError> {
+        let object = match self.object.ty {
+            typed_ast::Type::Ref(_) => self.object.codegen(ctx, context, module, builder)?,
+            _ => self
+                .object
+                .codegen_ptr_or_spill(ctx, context, module, builder)?,
+        };
+
+        Ok(LLVMBuildStructGEP(
+            builder,
+            object,
+            self.index as c_uint,
+            EMPTY_NAME,
+        ))
+    }
+}
+
+/// Load the function in `slot` of a vtable or interface table, as a
+/// pointer of type `func_ty`.
+pub unsafe fn build_table_lookup(
+    context: *mut llvm_sys::LLVMContext,
+    builder: *mut llvm_sys::LLVMBuilder,
+    table: LLVMValueRef,
+    slot: usize,
+    func_ty: LLVMTypeRef,
+) -> LLVMValueRef {
+    let index = LLVMConstInt(LLVMInt32TypeInContext(context), slot as c_ulonglong, 0);
+    let entry = LLVMBuildInBoundsGEP(builder, table, [index].as_mut_ptr(), 1, EMPTY_NAME);
+    let func = LLVMBuildLoad(builder, entry, EMPTY_NAME);
+    LLVMBuildBitCast(builder, func, func_ty, EMPTY_NAME)
+}
+
+/// The type of a pointer to an interface method's implementation, with `self`
+/// as an untyped object pointer.
+unsafe fn erased_method_type(
+    ctx: &mut CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    method_ty: &typed_ast::Type,
+) -> LLVMTypeRef {
+    let (params, return_type) = match method_ty {
+        typed_ast::Type::Function(params, return_type) => (params, return_type),
+        _ => unreachable!("interface methods are functions"),
+    };
+    let mut param_types: Vec<LLVMTypeRef> = params
+        .iter()
+        .map(|p| p.as_llvm_type(ctx, context))
+        .collect();
+    param_types[0] = LLVMPointerType(LLVMInt8TypeInContext(context), 0);
+    let func_type = LLVMFunctionType(
+        return_type.as_llvm_type(ctx, context),
+        param_types.as_mut_ptr(),
+        param_types.len() as c_uint,
+        0,
+    );
+    LLVMPointerType(func_type, 0)
+}
+
+/// Call the C `strlen` on `string`, giving an `int`. Uses the program's own
+/// `extern strlen` if there is one.
+unsafe fn build_strlen(
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    string: LLVMValueRef,
+) -> LLVMValueRef {
+    let name = std::ffi::CString::new("strlen").unwrap();
+    let mut func = LLVMGetNamedFunction(module, name.as_ptr());
+    if func.is_null() {
+        let size_type = LLVMIntPtrTypeInContext(context, LLVMGetModuleDataLayout(module));
+        let mut params = [LLVMPointerType(LLVMInt8TypeInContext(context), 0)];
+        let func_type = LLVMFunctionType(size_type, params.as_mut_ptr(), 1, 0);
+        func = LLVMAddFunction(module, name.as_ptr(), func_type);
+    }
+
+    let len = LLVMBuildCall(builder, func, [string].as_mut_ptr(), 1, EMPTY_NAME);
+    LLVMBuildIntCast(builder, len, LLVMInt32TypeInContext(context), EMPTY_NAME)
+}
+
+/// Lower `and`/`or` so that the right side only runs when it decides the result.
+unsafe fn build_short_circuit(
+    ctx: &mut CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    binary_expr: &typed_ast::BinaryExpr,
+) -> Result<LLVMValueRef, CodegenError> {
+    let is_and = binary_expr.op == typed_ast::BinOp::LogicalAnd;
+
+    let l_val = binary_expr.lhs.codegen(ctx, context, module, builder)?;
+    let lhs_block = LLVMGetInsertBlock(builder);
+    let rhs_block = LLVMAppendBasicBlockInContext(context, ctx.current_func(), EMPTY_NAME);
+    let end_block = LLVMAppendBasicBlockInContext(context, ctx.current_func(), EMPTY_NAME);
+
+    if is_and {
+        LLVMBuildCondBr(builder, l_val, rhs_block, end_block);
+    } else {
+        LLVMBuildCondBr(builder, l_val, end_block, rhs_block);
+    }
+
+    LLVMPositionBuilderAtEnd(builder, rhs_block);
+    let mark = ctx.temporary_mark();
+    let r_val = binary_expr.rhs.codegen(ctx, context, module, builder)?;
+    // Only this path makes the right side's temporaries, so destroy them here
+    build_temporary_cleanups(ctx, context, module, builder, mark);
+    // The right side may have added blocks of its own
+    let rhs_end_block = LLVMGetInsertBlock(builder);
+    LLVMBuildBr(builder, end_block);
+
+    LLVMPositionBuilderAtEnd(builder, end_block);
+    let i1_type = LLVMInt1TypeInContext(context);
+    let phi = LLVMBuildPhi(builder, i1_type, EMPTY_NAME);
+    // Skipping the right side means `and` was false or `or` was true
+    let short_val = LLVMConstInt(i1_type, (!is_and) as c_ulonglong, 0);
+    let mut values = [short_val, r_val];
+    let mut blocks = [lhs_block, rhs_end_block];
+    LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+
+    Ok(phi)
+}
+
+/// The comparison predicate for `op`, which must be a comparison operator.
+fn int_predicate(op: typed_ast::BinOp, unsigned: bool) -> LLVMIntPredicate {
+    use typed_ast::BinOp;
+
+    match (op, unsigned) {
+        (BinOp::Eq, _) => LLVMIntPredicate::LLVMIntEQ,
+        (BinOp::Ne, _) => LLVMIntPredicate::LLVMIntNE,
+        (BinOp::Gt, false) => LLVMIntPredicate::LLVMIntSGT,
+        (BinOp::Gt, true) => LLVMIntPredicate::LLVMIntUGT,
+        (BinOp::Gte, false) => LLVMIntPredicate::LLVMIntSGE,
+        (BinOp::Gte, true) => LLVMIntPredicate::LLVMIntUGE,
+        (BinOp::Lt, false) => LLVMIntPredicate::LLVMIntSLT,
+        (BinOp::Lt, true) => LLVMIntPredicate::LLVMIntULT,
+        (BinOp::Lte, false) => LLVMIntPredicate::LLVMIntSLE,
+        (BinOp::Lte, true) => LLVMIntPredicate::LLVMIntULE,
+        _ => unreachable!("{} is not a comparison", op),
+    }
+}
+
+/// Float arithmetic and comparisons. Comparisons with NaN are false, except
+/// for `!=`.
+unsafe fn build_float_binary(
+    builder: *mut llvm_sys::LLVMBuilder,
+    op: typed_ast::BinOp,
+    l_val: LLVMValueRef,
+    r_val: LLVMValueRef,
+) -> LLVMValueRef {
+    use llvm_sys::LLVMRealPredicate;
+    use typed_ast::BinOp;
+
+    let predicate = match op {
+        BinOp::Plus => return LLVMBuildFAdd(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Minus => return LLVMBuildFSub(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Times => return LLVMBuildFMul(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Divide => return LLVMBuildFDiv(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Remainder => return LLVMBuildFRem(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Eq => LLVMRealPredicate::LLVMRealOEQ,
+        BinOp::Ne => LLVMRealPredicate::LLVMRealUNE,
+        BinOp::Gt => LLVMRealPredicate::LLVMRealOGT,
+        BinOp::Gte => LLVMRealPredicate::LLVMRealOGE,
+        BinOp::Lt => LLVMRealPredicate::LLVMRealOLT,
+        BinOp::Lte => LLVMRealPredicate::LLVMRealOLE,
+        _ => unreachable!("{} is not a float operator", op),
+    };
+    LLVMBuildFCmp(builder, predicate, l_val, r_val, EMPTY_NAME)
+}
+
+/// Call the function `call` names with the already generated `args`.
+unsafe fn build_call(
+    ctx: &mut CompilerContext,
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    call: &typed_ast::FunctionCall,
+    mut args: Vec<LLVMValueRef>,
+) -> Result<LLVMValueRef, CodegenError> {
+    let func = match call.dispatch {
+        typed_ast::Dispatch::Static => call.name.codegen(ctx, context, module, builder)?,
+        typed_ast::Dispatch::Virtual(slot) => {
+            let vtable_field = LLVMBuildStructGEP(builder, args[0], 0, EMPTY_NAME);
+            let vtable = LLVMBuildLoad(builder, vtable_field, EMPTY_NAME);
+            let func_ty = call.name.ty.as_llvm_type(ctx, context);
+            build_table_lookup(context, builder, vtable, slot, func_ty)
+        }
+        // The implementation takes the object, not the interface pointer
+        typed_ast::Dispatch::Interface(slot) => {
+            let table = LLVMBuildExtractValue(builder, args[0], 1, EMPTY_NAME);
+            args[0] = LLVMBuildExtractValue(builder, args[0], 0, EMPTY_NAME);
+            let func_ty = erased_method_type(ctx, context, &call.name.ty);
+            build_table_lookup(context, builder, table, slot, func_ty)
+        }
+    };
+
+    Ok(LLVMBuildCall(
+        builder,
+        func,
+        args.as_mut_ptr(),
+        args.len() as c_uint,
+        EMPTY_NAME,
+    ))
+}
+
+unsafe fn build_int_arithmetic(
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    op: typed_ast::BinOp,
+    l_val: LLVMValueRef,
+    r_val: LLVMValueRef,
+    unsigned: bool,
+) -> LLVMValueRef {
+    use typed_ast::BinOp;
+
+    match op {
+        BinOp::Plus => return LLVMBuildAdd(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Minus => return LLVMBuildSub(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Times => return LLVMBuildMul(builder, l_val, r_val, EMPTY_NAME),
+        BinOp::Divide | BinOp::Remainder => (),
+        _ => unreachable!("{} is not an arithmetic operator", op),
+    }
+
+    build_zero_divisor_trap(context, module, builder, r_val);
+    if unsigned {
+        return match op {
+            BinOp::Divide => LLVMBuildUDiv(builder, l_val, r_val, EMPTY_NAME),
+            _ => LLVMBuildURem(builder, l_val, r_val, EMPTY_NAME),
+        };
+    }
+
+    // Dividing by -1 negates instead, so the minimum value wraps to itself
+    // rather than overflowing. Dividing by 1 gives the right remainder of 0.
+    let ty = LLVMTypeOf(r_val);
+    let minus_one = LLVMConstAllOnes(ty);
+    let is_minus_one = LLVMBuildICmp(
+        builder,
+        LLVMIntPredicate::LLVMIntEQ,
+        r_val,
+        minus_one,
+        EMPTY_NAME,
+    );
+    let divisor = LLVMBuildSelect(
+        builder,
+        is_minus_one,
+        LLVMConstInt(ty, 1, 0),
+        r_val,
+        EMPTY_NAME,
+    );
+    match op {
+        BinOp::Divide => {
+            let quotient = LLVMBuildSDiv(builder, l_val, divisor, EMPTY_NAME);
+            let negated = LLVMBuildNeg(builder, l_val, EMPTY_NAME);
+            LLVMBuildSelect(builder, is_minus_one, negated, quotient, EMPTY_NAME)
+        }
+        _ => LLVMBuildSRem(builder, l_val, divisor, EMPTY_NAME),
+    }
+}
+
+/// Stop the program with `llvm.trap` when `divisor` is zero.
+unsafe fn build_zero_divisor_trap(
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    builder: *mut llvm_sys::LLVMBuilder,
+    divisor: LLVMValueRef,
+) {
+    let name = std::ffi::CString::new("llvm.trap").unwrap();
+    let mut trap = LLVMGetNamedFunction(module, name.as_ptr());
+    if trap.is_null() {
+        let func_type = LLVMFunctionType(LLVMVoidTypeInContext(context), [].as_mut_ptr(), 0, 0);
+        trap = LLVMAddFunction(module, name.as_ptr(), func_type);
+    }
+
+    let func = LLVMGetBasicBlockParent(LLVMGetInsertBlock(builder));
+    let trap_block = LLVMAppendBasicBlockInContext(context, func, EMPTY_NAME);
+    let divide_block = LLVMAppendBasicBlockInContext(context, func, EMPTY_NAME);
+    let zero = LLVMConstNull(LLVMTypeOf(divisor));
+    let is_zero = LLVMBuildICmp(
+        builder,
+        LLVMIntPredicate::LLVMIntEQ,
+        divisor,
+        zero,
+        EMPTY_NAME,
+    );
+    LLVMBuildCondBr(builder, is_zero, trap_block, divide_block);
+
+    LLVMPositionBuilderAtEnd(builder, trap_block);
+    LLVMBuildCall(builder, trap, [].as_mut_ptr(), 0, EMPTY_NAME);
+    LLVMBuildUnreachable(builder);
+
+    LLVMPositionBuilderAtEnd(builder, divide_block);
+}
+
+/// Bring a shift amount to the width of the value being shifted and take it
+/// modulo that width, since LLVM gives poison for oversized shifts.
+unsafe fn shift_amount(
+    builder: *mut llvm_sys::LLVMBuilder,
+    binary_expr: &typed_ast::BinaryExpr,
+    l_val: LLVMValueRef,
+    r_val: LLVMValueRef,
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(l_val);
+    let signed = !binary_expr.rhs.ty.is_unsigned();
+    let amount = LLVMBuildIntCast2(builder, r_val, ty, signed as i32, EMPTY_NAME);
+    let bits = binary_expr.lhs.ty.int_bits().unwrap();
+    let mask = LLVMConstInt(ty, (bits - 1) as c_ulonglong, 0);
+    LLVMBuildAnd(builder, amount, mask, EMPTY_NAME)
+}
+
+/// Convert between the scalar types the type checker allows `as` on.
+unsafe fn build_cast(
+    builder: *mut llvm_sys::LLVMBuilder,
+    value: LLVMValueRef,
+    from: &typed_ast::Type,
+    to: &typed_ast::Type,
+    to_llvm: LLVMTypeRef,
+) -> LLVMValueRef {
+    use llvm_sys::LLVMOpcode;
+    use typed_ast::Type;
+
+    let is_pointer = |ty: &Type| matches!(ty, Type::Ref(_) | Type::CStr);
+
+    // Floats become integers by rounding towards zero
+    let float_opcode = match (from.is_float(), to.is_float()) {
+        (true, true) => return LLVMBuildFPCast(builder, value, to_llvm, EMPTY_NAME),
+        (true, false) if to.is_unsigned() => Some(LLVMOpcode::LLVMFPToUI),
+        (true, false) => Some(LLVMOpcode::LLVMFPToSI),
+        (false, true) if from.is_unsigned() => Some(LLVMOpcode::LLVMUIToFP),
+        (false, true) => Some(LLVMOpcode::LLVMSIToFP),
+        (false, false) => None,
+    };
+    if let Some(opcode) = float_opcode {
+        return LLVMBuildCast(builder, opcode, value, to_llvm, EMPTY_NAME);
+    }
+
+    let opcode = match (is_pointer(from), is_pointer(to)) {
+        (true, true) => LLVMOpcode::LLVMBitCast,
+        (true, false) => LLVMOpcode::LLVMPtrToInt,
+        (false, true) => LLVMOpcode::LLVMIntToPtr,
+        // Anything non-zero is true
+        (false, false) if *to == Type::Bool => {
+            let zero = LLVMConstNull(LLVMTypeOf(value));
+            return LLVMBuildICmp(
+                builder,
+                LLVMIntPredicate::LLVMIntNE,
+                value,
+                zero,
+                EMPTY_NAME,
+            );
+        }
+        // Picks trunc, sext or zext from the widths, or nothing if they match
+        (false, false) => {
+            let signed = !from.is_unsigned();
+            return LLVMBuildIntCast2(builder, value, to_llvm, signed as i32, EMPTY_NAME);
+        }
+    };
+
+    LLVMBuildCast(builder, opcode, value, to_llvm, EMPTY_NAME)
+}
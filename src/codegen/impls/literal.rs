@@ -1,18 +1,17 @@
-use super::EMPTY_NAME;
 use crate::codegen::context::CompilerContext;
 use crate::type_checker::typed_ast;
 
-use std::os::raw::c_ulonglong;
+use std::os::raw::{c_uint, c_ulonglong};
 
 use llvm_sys::core::*;
 
 impl typed_ast::Literal {
     pub unsafe fn codegen(
         &self,
-        ctx: &CompilerContext,
+        ctx: &mut CompilerContext,
         context: *mut llvm_sys::LLVMContext,
-        _module: *mut llvm_sys::LLVMModule,
-        builder: *mut llvm_sys::LLVMBuilder,
+        module: *mut llvm_sys::LLVMModule,
+        _builder: *mut llvm_sys::LLVMBuilder,
     ) -> llvm_sys::prelude::LLVMValueRef {
         use typed_ast::LiteralInner;
 
@@ -26,17 +25,7 @@ impl typed_ast::Literal {
                 // TODO: Handle error
                 LLVMConstInt(ty, *value as c_ulonglong, 1)
             }
-            // TODO: Crashes program if not in a function
-            LiteralInner::CStr(string) => {
-                use std::ffi::CString;
-                // TODO: Handle this error
-                let converted_string = CString::new(string.as_bytes()).unwrap();
-                LLVMBuildGlobalStringPtr(
-                    builder,
-                    converted_string.as_ptr() as *const i8,
-                    EMPTY_NAME,
-                )
-            }
+            LiteralInner::CStr(string) => global_string(context, module, string),
             LiteralInner::Bool(val) => {
                 let i1_type = LLVMInt1TypeInContext(context);
                 LLVMConstInt(i1_type, *val as c_ulonglong, 0)
@@ -77,7 +66,52 @@ impl typed_ast::Literal {
                 let u64_type = LLVMInt64TypeInContext(context);
                 LLVMConstInt(u64_type, *val as c_ulonglong, 0)
             }
-            LiteralInner::Str(_val) => todo!("String struct not done yet"),
+            LiteralInner::Float(val) => {
+                LLVMConstReal(LLVMFloatTypeInContext(context), (*val).into())
+            }
+            LiteralInner::Double(val) => LLVMConstReal(LLVMDoubleTypeInContext(context), *val),
+            LiteralInner::Char(val) => {
+                let char_type = LLVMInt8TypeInContext(context);
+                LLVMConstInt(char_type, *val as c_ulonglong, 0)
+            }
+            LiteralInner::Str(string) => {
+                let data = global_string(context, module, string);
+                let len = LLVMConstInt(
+                    LLVMInt32TypeInContext(context),
+                    string.len() as c_ulonglong,
+                    0,
+                );
+                let mut fields = [data, len];
+                LLVMConstStructInContext(context, fields.as_mut_ptr(), 2, 0)
+            }
         }
     }
 }
+
+/// Put a NUL-terminated string in a private global and return a pointer to
+/// its first byte. Needs no builder, so it also works outside functions.
+unsafe fn global_string(
+    context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    string: &str,
+) -> llvm_sys::prelude::LLVMValueRef {
+    use llvm_sys::LLVMLinkage;
+    use std::ffi::CString;
+
+    let data = LLVMConstStringInContext(
+        context,
+        string.as_ptr() as *const _,
+        string.len() as c_uint,
+        0,
+    );
+    let name = CString::new(".str").unwrap();
+    let global = LLVMAddGlobal(module, LLVMTypeOf(data), name.as_ptr());
+    LLVMSetInitializer(global, data);
+    LLVMSetGlobalConstant(global, 1);
+    LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+    LLVMSetUnnamedAddress(global, llvm_sys::LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+
+    let zero = LLVMConstInt(LLVMInt32TypeInContext(context), 0, 0);
+    let mut indices = [zero, zero];
+    LLVMConstInBoundsGEP(global, indices.as_mut_ptr(), indices.len() as c_uint)
+}
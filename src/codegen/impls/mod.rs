@@ -1,3 +1,4 @@
+pub mod drop;
 pub mod expr;
 pub mod literal;
 pub mod stmt;
@@ -5,3 +6,25 @@ pub mod top_level;
 pub mod type_to_llvm;
 
 use super::EMPTY_NAME;
+
+use llvm_sys::core::*;
+use llvm_sys::prelude::{LLVMBuilderRef, LLVMTypeRef, LLVMValueRef};
+
+/// Allocate stack space in the entry block of the current function, so that an
+/// alloca reached inside a loop does not grow the stack on every iteration.
+unsafe fn build_entry_alloca(builder: LLVMBuilderRef, ty: LLVMTypeRef) -> LLVMValueRef {
+    let func = LLVMGetBasicBlockParent(LLVMGetInsertBlock(builder));
+    let entry = LLVMGetEntryBasicBlock(func);
+
+    let entry_builder = LLVMCreateBuilderInContext(LLVMGetTypeContext(ty));
+    let first = LLVMGetFirstInstruction(entry);
+    if first.is_null() {
+        LLVMPositionBuilderAtEnd(entry_builder, entry);
+    } else {
+        LLVMPositionBuilderBefore(entry_builder, first);
+    }
+    let alloca = LLVMBuildAlloca(entry_builder, ty, EMPTY_NAME);
+    LLVMDisposeBuilder(entry_builder);
+
+    alloca
+}
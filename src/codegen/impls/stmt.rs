@@ -1,11 +1,14 @@
-use super::EMPTY_NAME;
+use super::{build_entry_alloca, EMPTY_NAME};
 use crate::codegen::context::CompilerContext;
 use crate::codegen::error::CodegenError;
 use crate::type_checker::typed_ast;
 
-use crate::codegen::symbol::{Symbol, SymbolType};
+use super::drop::{build_cleanups, build_temporary_cleanups, is_terminated};
+use crate::codegen::context::LoopTarget;
+use crate::codegen::symbol::{Cleanup, Symbol, SymbolType};
 
 use llvm_sys::core::*;
+use llvm_sys::LLVMIntPredicate;
 
 impl typed_ast::Stmt {
     pub unsafe fn codegen(
@@ -16,7 +19,12 @@ impl typed_ast::Stmt {
         builder: *mut llvm_sys::LLVMBuilder,
     ) -> Result<(), CodegenError> {
         use typed_ast::Stmt;
+        // Nothing after a return or jump in the same block can run
+        if is_terminated(builder) {
+            return Ok(());
+        }
 
+        let mark = ctx.temporary_mark();
         match self {
             Stmt::ExprStmt(expr) => {
                 expr.codegen(ctx, llvm_context, module, builder)?;
@@ -25,12 +33,11 @@ impl typed_ast::Stmt {
                 block.codegen(ctx, llvm_context, module, builder)?;
             }
             Stmt::ConstDef(def) => {
-                let alloca = LLVMBuildAlloca(
-                    builder,
-                    def.binding.ty.as_llvm_type(ctx, llvm_context),
-                    EMPTY_NAME,
-                );
-                let val = def.value.codegen(ctx, llvm_context, module, builder)?;
+                let alloca =
+                    build_entry_alloca(builder, def.binding.ty.as_llvm_type(ctx, llvm_context));
+                let val = def
+                    .value
+                    .codegen_owned(ctx, llvm_context, module, builder)?;
 
                 LLVMBuildStore(builder, val, alloca);
 
@@ -40,14 +47,17 @@ impl typed_ast::Stmt {
                         Symbol::new(alloca, SymbolType::Const),
                     )
                     .unwrap();
+                if ctx.needs_drop(&def.binding.ty) {
+                    ctx.symbols
+                        .add_cleanup(Cleanup::new(alloca, def.binding.ty.clone()));
+                }
             }
             Stmt::VarDef(def) => {
-                let alloca = LLVMBuildAlloca(
-                    builder,
-                    def.binding.ty.as_llvm_type(ctx, llvm_context),
-                    EMPTY_NAME,
-                );
-                let val = def.value.codegen(ctx, llvm_context, module, builder)?;
+                let alloca =
+                    build_entry_alloca(builder, def.binding.ty.as_llvm_type(ctx, llvm_context));
+                let val = def
+                    .value
+                    .codegen_owned(ctx, llvm_context, module, builder)?;
 
                 LLVMBuildStore(builder, val, alloca);
 
@@ -57,9 +67,29 @@ impl typed_ast::Stmt {
                         Symbol::new(alloca, SymbolType::Var),
                     )
                     .unwrap();
+                if ctx.needs_drop(&def.binding.ty) {
+                    ctx.symbols
+                        .add_cleanup(Cleanup::new(alloca, def.binding.ty.clone()));
+                }
             }
-            Stmt::ReturnStmt(expr) => {
-                let value = expr.codegen(ctx, llvm_context, module, builder)?;
+            Stmt::ReturnStmt(stmt) => {
+                let value = stmt
+                    .value
+                    .codegen_owned(ctx, llvm_context, module, builder)?;
+                build_temporary_cleanups(ctx, llvm_context, module, builder, mark);
+
+                // A local that is returned is moved out rather than destroyed
+                let returned = match &stmt.value.val {
+                    typed_ast::ExprInner::Ident(name) => {
+                        ctx.symbols.get_symbol(name).map(|symbol| symbol.value)
+                    }
+                    _ => None,
+                };
+                let cleanups = ctx
+                    .symbols
+                    .cleanups_from(1)
+                    .filter(|cleanup| Some(cleanup.ptr) != returned);
+                build_cleanups(ctx, llvm_context, module, builder, cleanups);
                 LLVMBuildRet(builder, value);
             }
             Stmt::IfStmt(if_stmt) => {
@@ -68,7 +98,22 @@ impl typed_ast::Stmt {
             Stmt::WhileStmt(while_stmt) => {
                 while_stmt.codegen(ctx, llvm_context, module, builder)?;
             }
+            Stmt::ForStmt(for_stmt) => {
+                for_stmt.codegen(ctx, llvm_context, module, builder)?;
+            }
+            Stmt::BreakStmt(jump) | Stmt::ContinueStmt(jump) => {
+                let target = ctx.loop_target(jump.label.as_deref());
+                let (block, depth) = match self {
+                    Stmt::BreakStmt(_) => (target.break_block, target.depth),
+                    _ => (target.continue_block, target.depth),
+                };
+                // Leaving the loop body destroys the locals declared in it
+                let cleanups = ctx.symbols.cleanups_from(depth);
+                build_cleanups(ctx, llvm_context, module, builder, cleanups);
+                LLVMBuildBr(builder, block);
+            }
         }
+        build_temporary_cleanups(ctx, llvm_context, module, builder, mark);
 
         Ok(())
     }
@@ -82,10 +127,21 @@ impl typed_ast::BlockStmt {
         module: *mut llvm_sys::LLVMModule,
         builder: *mut llvm_sys::LLVMBuilder,
     ) -> Result<(), CodegenError> {
+        ctx.symbols.push_scope();
         for stmt in self.inners.iter() {
             stmt.codegen(ctx, llvm_context, module, builder)?;
         }
 
+        let depth = ctx.symbols.depth() - 1;
+        build_cleanups(
+            ctx,
+            llvm_context,
+            module,
+            builder,
+            ctx.symbols.cleanups_from(depth),
+        );
+        ctx.symbols.pop_scope().unwrap();
+
         Ok(())
     }
 }
@@ -98,7 +154,9 @@ impl typed_ast::IfStmt {
         module: *mut llvm_sys::LLVMModule,
         builder: *mut llvm_sys::LLVMBuilder,
     ) -> Result<(), CodegenError> {
+        let mark = ctx.temporary_mark();
         let cond = self.condition.codegen(ctx, llvm_context, module, builder)?;
+        build_temporary_cleanups(ctx, llvm_context, module, builder, mark);
 
         let if_block = LLVMAppendBasicBlockInContext(llvm_context, ctx.current_func(), EMPTY_NAME);
         let else_block =
@@ -111,7 +169,10 @@ impl typed_ast::IfStmt {
         // Gen if block
         LLVMPositionBuilderAtEnd(builder, if_block);
         self.body.codegen(ctx, llvm_context, module, builder)?;
-        LLVMBuildBr(builder, final_block);
+        let mut falls_through = !is_terminated(builder);
+        if falls_through {
+            LLVMBuildBr(builder, final_block);
+        }
 
         // Gen else block
         LLVMPositionBuilderAtEnd(builder, else_block);
@@ -127,9 +188,16 @@ impl typed_ast::IfStmt {
             }
         }
 
-        LLVMBuildBr(builder, final_block);
+        if !is_terminated(builder) {
+            falls_through = true;
+            LLVMBuildBr(builder, final_block);
+        }
 
         LLVMPositionBuilderAtEnd(builder, final_block);
+        // Both branches returned or jumped, so the code after this never runs
+        if !falls_through {
+            LLVMBuildUnreachable(builder);
+        }
 
         Ok(())
     }
@@ -153,15 +221,27 @@ impl typed_ast::WhileStmt {
 
         // Conditional break
         LLVMPositionBuilderAtEnd(builder, condition_block);
+        let mark = ctx.temporary_mark();
         let condition = self.condition.codegen(ctx, llvm_context, module, builder)?;
+        build_temporary_cleanups(ctx, llvm_context, module, builder, mark);
         // If condition == 1 then go to body block, else go to final block
         LLVMBuildCondBr(builder, condition, body_block, final_block);
 
         // Body block
         LLVMPositionBuilderAtEnd(builder, body_block);
-        self.body.codegen(ctx, llvm_context, module, builder)?;
+        ctx.push_loop(LoopTarget {
+            label: self.label.clone(),
+            break_block: final_block,
+            continue_block: condition_block,
+            depth: ctx.symbols.depth(),
+        });
+        let result = self.body.codegen(ctx, llvm_context, module, builder);
+        ctx.pop_loop();
+        result?;
         // Go back to condition check
-        LLVMBuildBr(builder, condition_block);
+        if !is_terminated(builder) {
+            LLVMBuildBr(builder, condition_block);
+        }
 
         // Exit
         LLVMPositionBuilderAtEnd(builder, final_block);
@@ -169,3 +249,121 @@ impl typed_ast::WhileStmt {
         Ok(())
     }
 }
+
+impl typed_ast::ForStmt {
+    /// Both kinds of loop count an index up from the start of the range or
+    /// array, and the loop variable is only visible inside the body.
+    pub unsafe fn codegen(
+        &self,
+        ctx: &mut CompilerContext,
+        llvm_context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<(), CodegenError> {
+        use typed_ast::ForIter;
+
+        // Holds the array looped over if it is a temporary, outside the body's scope
+        ctx.symbols.push_scope();
+        let (start, end, array) = match &self.iter {
+            ForIter::Range(start, end) => (
+                start.codegen(ctx, llvm_context, module, builder)?,
+                end.codegen(ctx, llvm_context, module, builder)?,
+                None,
+            ),
+            ForIter::Array(array) => {
+                let len = match array.ty {
+                    typed_ast::Type::Array(_, len) => len,
+                    _ => unreachable!("only arrays are looped over"),
+                };
+                let i32_type = LLVMInt32TypeInContext(llvm_context);
+                let mark = ctx.temporary_mark();
+                let data = array.codegen_ptr_or_spill(ctx, llvm_context, module, builder)?;
+                // A `return` in the body runs scope cleanups, not the statement's temporaries
+                for temporary in ctx.take_temporaries(mark) {
+                    ctx.symbols.add_cleanup(temporary);
+                }
+                (
+                    LLVMConstInt(i32_type, 0, 0),
+                    LLVMConstInt(i32_type, len as u64, 0),
+                    Some(data),
+                )
+            }
+        };
+        // A range's loop variable is the counter itself, since the body can't change it
+        let counter = build_entry_alloca(builder, LLVMTypeOf(start));
+        LLVMBuildStore(builder, start, counter);
+        let variable = match array {
+            Some(_) => build_entry_alloca(builder, self.binding.ty.as_llvm_type(ctx, llvm_context)),
+            None => counter,
+        };
+
+        let condition_block =
+            LLVMAppendBasicBlockInContext(llvm_context, ctx.current_func(), EMPTY_NAME);
+        let body_block =
+            LLVMAppendBasicBlockInContext(llvm_context, ctx.current_func(), EMPTY_NAME);
+        let step_block =
+            LLVMAppendBasicBlockInContext(llvm_context, ctx.current_func(), EMPTY_NAME);
+        let final_block =
+            LLVMAppendBasicBlockInContext(llvm_context, ctx.current_func(), EMPTY_NAME);
+        LLVMBuildBr(builder, condition_block);
+
+        LLVMPositionBuilderAtEnd(builder, condition_block);
+        let index = LLVMBuildLoad(builder, counter, EMPTY_NAME);
+        let predicate = if self.binding.ty.is_unsigned() || array.is_some() {
+            LLVMIntPredicate::LLVMIntULT
+        } else {
+            LLVMIntPredicate::LLVMIntSLT
+        };
+        let condition = LLVMBuildICmp(builder, predicate, index, end, EMPTY_NAME);
+        LLVMBuildCondBr(builder, condition, body_block, final_block);
+
+        LLVMPositionBuilderAtEnd(builder, body_block);
+        if let Some(data) = array {
+            let zero = LLVMConstInt(LLVMTypeOf(index), 0, 0);
+            let mut indices = [zero, index];
+            let item_ptr = LLVMBuildGEP(builder, data, indices.as_mut_ptr(), 2, EMPTY_NAME);
+            let item = LLVMBuildLoad(builder, item_ptr, EMPTY_NAME);
+            LLVMBuildStore(builder, item, variable);
+        }
+        ctx.push_loop(LoopTarget {
+            label: self.label.clone(),
+            break_block: final_block,
+            continue_block: step_block,
+            depth: ctx.symbols.depth(),
+        });
+        ctx.symbols.push_scope();
+        ctx.symbols
+            .add_symbol(
+                self.binding.name.clone(),
+                Symbol::new(variable, SymbolType::Const),
+            )
+            .unwrap();
+        let result = self.body.codegen(ctx, llvm_context, module, builder);
+        ctx.symbols.pop_scope().unwrap();
+        ctx.pop_loop();
+        result?;
+        if !is_terminated(builder) {
+            LLVMBuildBr(builder, step_block);
+        }
+
+        LLVMPositionBuilderAtEnd(builder, step_block);
+        let index = LLVMBuildLoad(builder, counter, EMPTY_NAME);
+        let one = LLVMConstInt(LLVMTypeOf(index), 1, 0);
+        let next = LLVMBuildAdd(builder, index, one, EMPTY_NAME);
+        LLVMBuildStore(builder, next, counter);
+        LLVMBuildBr(builder, condition_block);
+
+        LLVMPositionBuilderAtEnd(builder, final_block);
+        let depth = ctx.symbols.depth() - 1;
+        build_cleanups(
+            ctx,
+            llvm_context,
+            module,
+            builder,
+            ctx.symbols.cleanups_from(depth),
+        );
+        ctx.symbols.pop_scope().unwrap();
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
-use super::EMPTY_NAME;
+use super::drop::{build_cleanups, is_terminated};
+use super::expr::build_table_lookup;
+use super::{build_entry_alloca, EMPTY_NAME};
 use crate::codegen::context::CompilerContext;
 use crate::codegen::error::CodegenError;
 use crate::type_checker::typed_ast;
@@ -8,7 +10,8 @@ use crate::codegen::symbol::{Symbol, SymbolType};
 use std::os::raw::c_uint;
 
 use llvm_sys::core::*;
-use llvm_sys::prelude::LLVMTypeRef;
+use llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
+use llvm_sys::LLVMLinkage;
 
 impl typed_ast::Program {
     pub unsafe fn codegen(
@@ -18,11 +21,35 @@ impl typed_ast::Program {
         module: *mut llvm_sys::LLVMModule,
         builder: *mut llvm_sys::LLVMBuilder,
     ) -> Result<(), CodegenError> {
+        use typed_ast::TopLevelStmt;
+
+        // Declare everything first so that bodies can refer to anything at the top level
+        for stmt in self.0.iter() {
+            match stmt {
+                TopLevelStmt::ClassDef(def) => def.declare(ctx, context),
+                TopLevelStmt::InterfaceDef(def) => ctx.add_interface(def.clone()),
+                _ => (),
+            }
+        }
         for stmt in self.0.iter() {
-            use typed_ast::TopLevelStmt;
+            match stmt {
+                TopLevelStmt::FunctionDef(def) => def.declare(ctx, context, module),
+                TopLevelStmt::ExternDef(def) => def.declare(ctx, context, module),
+                TopLevelStmt::ConstDef(def) => def.declare(ctx, context, module),
+                _ => (),
+            }
+        }
 
+        // Class bodies and vtables, which need every function declared
+        for stmt in self.0.iter() {
+            if let TopLevelStmt::ClassDef(def) = stmt {
+                def.codegen(ctx, context, module, builder)?;
+            }
+        }
+
+        for stmt in self.0.iter() {
             match stmt {
-                TopLevelStmt::ClassDef(def) => def.codegen(ctx, context, module, builder),
+                TopLevelStmt::ClassDef(_) | TopLevelStmt::InterfaceDef(_) => Ok(()),
                 TopLevelStmt::FunctionDef(def) => def.codegen(ctx, context, module, builder),
                 TopLevelStmt::ExternDef(def) => def.codegen(ctx, context, module, builder),
                 TopLevelStmt::ConstDef(def) => def.codegen(ctx, context, module, builder),
@@ -34,21 +61,35 @@ impl typed_ast::Program {
 }
 
 impl typed_ast::ClassDef {
-    pub unsafe fn codegen(
+    /// Create the named struct without a body so that other types can refer to it.
+    pub unsafe fn declare(
         &self,
         ctx: &mut CompilerContext,
         llvm_context: *mut llvm_sys::LLVMContext,
-        _module: *mut llvm_sys::LLVMModule,
-        _builder: *mut llvm_sys::LLVMBuilder,
-    ) -> Result<(), CodegenError> {
+    ) {
         use std::ffi::CString;
         let c_name = CString::new(self.name.as_bytes()).unwrap();
-        let struct_ty = LLVMStructCreateNamed(llvm_context, c_name.as_ptr() as *const i8);
+        let struct_ty = LLVMStructCreateNamed(llvm_context, c_name.as_ptr());
+        ctx.add_class(struct_ty, self.clone());
+    }
+
+    /// Fill in the struct's fields, and emit its vtable and interface tables.
+    pub unsafe fn codegen(
+        &self,
+        ctx: &mut CompilerContext,
+        llvm_context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<(), CodegenError> {
+        let struct_ty = ctx.class(&self.name).unwrap().0;
         let mut element_types: Vec<LLVMTypeRef> = self
             .fields
             .iter()
-            .map(|(_, ty)| ty.as_llvm_type(ctx, llvm_context))
+            .map(|f| f.ty.as_llvm_type(ctx, llvm_context))
             .collect();
+        if self.has_vtable {
+            element_types.insert(0, vtable_ptr_type(llvm_context));
+        }
         LLVMStructSetBody(
             struct_ty,
             element_types.as_mut_ptr(),
@@ -56,20 +97,140 @@ impl typed_ast::ClassDef {
             0,
         );
 
-        ctx.add_class(struct_ty, self.clone());
+        if self.has_vtable {
+            let funcs = self
+                .vtable()
+                .iter()
+                .map(|method| {
+                    let name = typed_ast::method_symbol(&method.owner, &method.name);
+                    ctx.symbols.get_symbol(&name).unwrap().value
+                })
+                .collect();
+            let vtable = method_table(
+                llvm_context,
+                module,
+                &format!("{}.vtable", self.name),
+                funcs,
+            );
+            ctx.add_vtable(self.name.clone(), vtable);
+        }
+
+        if ctx.needs_drop(&typed_ast::Type::Class(self.name.clone())) {
+            self.define_drop(ctx, llvm_context, module, builder);
+        }
+
+        for interface in self.interfaces.iter() {
+            let mut funcs = vec![];
+            for required in ctx.interface(interface).unwrap().methods.clone() {
+                let method = self.method(&required.name).unwrap();
+                funcs.push(match method.slot {
+                    // The object may be of a derived class, so look it up again
+                    Some(slot) => {
+                        self.virtual_thunk(ctx, llvm_context, module, builder, method, slot)
+                    }
+                    None => {
+                        let name = typed_ast::method_symbol(&method.owner, &method.name);
+                        ctx.symbols.get_symbol(&name).unwrap().value
+                    }
+                });
+            }
+            let name = format!("{}.{}.table", self.name, interface);
+            let table = method_table(llvm_context, module, &name, funcs);
+            ctx.add_interface_table(self.name.clone(), interface.clone(), table);
+        }
 
         Ok(())
     }
+
+    /// A function that calls `method` through the vtable of its `self`.
+    unsafe fn virtual_thunk(
+        &self,
+        ctx: &CompilerContext,
+        llvm_context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+        method: &typed_ast::Method,
+        slot: usize,
+    ) -> LLVMValueRef {
+        use std::ffi::CString;
+        let name = format!("{}.{}.virtual", self.name, method.name);
+        let c_name = CString::new(name.as_bytes()).unwrap();
+        let existing = LLVMGetNamedFunction(module, c_name.as_ptr());
+        if !existing.is_null() {
+            return existing;
+        }
+
+        let func_ptr_ty = method.ty.as_llvm_type(ctx, llvm_context);
+        let func = LLVMAddFunction(module, c_name.as_ptr(), LLVMGetElementType(func_ptr_ty));
+        LLVMSetLinkage(func, LLVMLinkage::LLVMPrivateLinkage);
+        let block = LLVMAppendBasicBlockInContext(llvm_context, func, EMPTY_NAME);
+        LLVMPositionBuilderAtEnd(builder, block);
+
+        let mut args: Vec<LLVMValueRef> = (0..LLVMCountParams(func))
+            .map(|i| LLVMGetParam(func, i))
+            .collect();
+        let vtable_field = LLVMBuildStructGEP(builder, args[0], 0, EMPTY_NAME);
+        let vtable = LLVMBuildLoad(builder, vtable_field, EMPTY_NAME);
+        let target = build_table_lookup(llvm_context, builder, vtable, slot, func_ptr_ty);
+        let result = LLVMBuildCall(
+            builder,
+            target,
+            args.as_mut_ptr(),
+            args.len() as c_uint,
+            EMPTY_NAME,
+        );
+        match &method.ty {
+            typed_ast::Type::Function(_, return_type)
+                if **return_type == typed_ast::Type::NoneType =>
+            {
+                LLVMBuildRetVoid(builder)
+            }
+            _ => LLVMBuildRet(builder, result),
+        };
+
+        func
+    }
+}
+
+/// A constant array of functions, used to look them up by slot at runtime.
+/// Gives a pointer to its first entry.
+unsafe fn method_table(
+    llvm_context: *mut llvm_sys::LLVMContext,
+    module: *mut llvm_sys::LLVMModule,
+    name: &str,
+    funcs: Vec<LLVMValueRef>,
+) -> LLVMValueRef {
+    let entry_ty = LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0);
+    let mut entries: Vec<LLVMValueRef> = funcs
+        .into_iter()
+        .map(|func| LLVMConstBitCast(func, entry_ty))
+        .collect();
+    let table = LLVMConstArray(entry_ty, entries.as_mut_ptr(), entries.len() as c_uint);
+
+    use std::ffi::CString;
+    let c_name = CString::new(name.as_bytes()).unwrap();
+    let global = LLVMAddGlobal(module, LLVMTypeOf(table), c_name.as_ptr());
+    LLVMSetInitializer(global, table);
+    LLVMSetGlobalConstant(global, 1);
+    LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+
+    let zero = LLVMConstInt(LLVMInt32TypeInContext(llvm_context), 0, 0);
+    LLVMConstInBoundsGEP(global, [zero, zero].as_mut_ptr(), 2)
+}
+
+/// The type of the vtable pointer at the start of objects that have one.
+unsafe fn vtable_ptr_type(context: *mut llvm_sys::LLVMContext) -> LLVMTypeRef {
+    LLVMPointerType(LLVMPointerType(LLVMInt8TypeInContext(context), 0), 0)
 }
 
 impl typed_ast::FunctionDef {
-    pub unsafe fn codegen(
+    /// Add the function to the module and symbol table without a body.
+    pub unsafe fn declare(
         &self,
         ctx: &mut CompilerContext,
         context: *mut llvm_sys::LLVMContext,
         module: *mut llvm_sys::LLVMModule,
-        builder: *mut llvm_sys::LLVMBuilder,
-    ) -> Result<(), CodegenError> {
+    ) {
         // Turn args into vec of llvm types
         let mut args: Vec<LLVMTypeRef> = self
             .params
@@ -85,7 +246,22 @@ impl typed_ast::FunctionDef {
         use std::ffi::CString;
         let converted = CString::new(self.name.as_bytes()).unwrap();
 
-        let func = LLVMAddFunction(module, converted.as_ptr() as *const i8, func_type);
+        let func = LLVMAddFunction(module, converted.as_ptr(), func_type);
+
+        // Add function to symbol table so that it can be called.
+        ctx.symbols
+            .add_symbol(self.name.clone(), Symbol::new(func, SymbolType::Func))
+            .unwrap();
+    }
+
+    pub unsafe fn codegen(
+        &self,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+        builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<(), CodegenError> {
+        let func = ctx.symbols.get_symbol(&self.name).unwrap().value;
         let block = LLVMAppendBasicBlockInContext(context, func, EMPTY_NAME);
         LLVMPositionBuilderAtEnd(builder, block);
 
@@ -95,7 +271,7 @@ impl typed_ast::FunctionDef {
 
         // Add arguments to current scope
         for (i, param) in self.params.iter().enumerate() {
-            let alloca = LLVMBuildAlloca(builder, param.ty.as_llvm_type(ctx, context), EMPTY_NAME);
+            let alloca = build_entry_alloca(builder, param.ty.as_llvm_type(ctx, context));
             let value = LLVMGetParam(func, i as u32);
             LLVMBuildStore(builder, value, alloca);
             ctx.symbols
@@ -107,31 +283,35 @@ impl typed_ast::FunctionDef {
             stmt.codegen(ctx, context, module, builder)?;
         }
 
-        ctx.symbols.pop_scope().unwrap();
-        ctx.pop_func();
-
         // Add ret void if it is a void function so that LLVM is happy.
         if matches!(self.return_type, typed_ast::Type::NoneType) {
-            LLVMBuildRetVoid(builder);
+            let depth = ctx.symbols.depth() - 1;
+            build_cleanups(
+                ctx,
+                context,
+                module,
+                builder,
+                ctx.symbols.cleanups_from(depth),
+            );
+            if !is_terminated(builder) {
+                LLVMBuildRetVoid(builder);
+            }
         }
 
-        // Add function to symbol table so that it can be called.
-        ctx.symbols
-            .add_symbol(self.name.clone(), Symbol::new(func, SymbolType::Func))
-            .unwrap();
+        ctx.symbols.pop_scope().unwrap();
+        ctx.pop_func();
 
         Ok(())
     }
 }
 
 impl typed_ast::ExternDef {
-    pub unsafe fn codegen(
+    pub unsafe fn declare(
         &self,
         ctx: &mut CompilerContext,
         context: *mut llvm_sys::LLVMContext,
         module: *mut llvm_sys::LLVMModule,
-        _builder: *mut llvm_sys::LLVMBuilder,
-    ) -> Result<(), CodegenError> {
+    ) {
         let mut args: Vec<LLVMTypeRef> = self
             .params
             .iter()
@@ -144,23 +324,62 @@ impl typed_ast::ExternDef {
             return_type,
             args.as_mut_ptr(),
             args.len() as c_uint,
-            0, // TODO: Support varargs
+            self.is_variadic as i32,
         );
 
         // Convert name to C string
         use std::ffi::CString;
         let converted = CString::new(self.name.as_bytes()).unwrap();
 
-        let func = LLVMAddFunction(module, converted.as_ptr() as *const i8, func_type);
+        let func = LLVMAddFunction(module, converted.as_ptr(), func_type);
         ctx.symbols
             .add_symbol(self.name.clone(), Symbol::new(func, SymbolType::Func))
             .unwrap();
+    }
 
+    /// Externs have no body, so everything happens in [`Self::declare`].
+    pub unsafe fn codegen(
+        &self,
+        _ctx: &mut CompilerContext,
+        _context: *mut llvm_sys::LLVMContext,
+        _module: *mut llvm_sys::LLVMModule,
+        _builder: *mut llvm_sys::LLVMBuilder,
+    ) -> Result<(), CodegenError> {
         Ok(())
     }
 }
 
 impl typed_ast::GlobalConstDef {
+    /// Lower to an internal constant global. The value is already known, so
+    /// LLVM can fold loads of it into its uses.
+    pub unsafe fn declare(
+        &self,
+        ctx: &mut CompilerContext,
+        context: *mut llvm_sys::LLVMContext,
+        module: *mut llvm_sys::LLVMModule,
+    ) {
+        use llvm_sys::LLVMLinkage;
+        use std::ffi::CString;
+
+        let ty = self.binding.ty.as_llvm_type(ctx, context);
+        let name = CString::new(self.binding.name.as_bytes()).unwrap();
+        let global = LLVMAddGlobal(module, ty, name.as_ptr());
+        let value = self
+            .value
+            .codegen(ctx, context, module, std::ptr::null_mut());
+        LLVMSetInitializer(global, value);
+        LLVMSetGlobalConstant(global, 1);
+        LLVMSetLinkage(global, LLVMLinkage::LLVMInternalLinkage);
+
+        ctx.symbols
+            .add_symbol(
+                self.binding.name.clone(),
+                Symbol::new(global, SymbolType::Const),
+            )
+            .unwrap();
+    }
+
+    /// Globals have no code of their own, see [`Self::declare`].
     pub unsafe fn codegen(
         &self,
         _ctx: &mut CompilerContext,
@@ -168,6 +387,6 @@ impl typed_ast::GlobalConstDef {
         _module: *mut llvm_sys::LLVMModule,
         _builder: *mut llvm_sys::LLVMBuilder,
     ) -> Result<(), CodegenError> {
-        todo!();
+        Ok(())
     }
 }
@@ -25,15 +25,48 @@ impl Type {
             Type::Int32 | Type::UInt32 => LLVMInt32TypeInContext(llvm_context),
             Type::Int64 | Type::UInt64 => LLVMInt64TypeInContext(llvm_context),
             Type::Bool => LLVMInt1TypeInContext(llvm_context),
+            Type::Float => LLVMFloatTypeInContext(llvm_context),
+            Type::Double => LLVMDoubleTypeInContext(llvm_context),
             Type::Array(inner, len) => {
                 let inner_type = inner.as_llvm_type(ctx, llvm_context);
                 LLVMArrayType(inner_type, *len as c_uint)
             }
+            // { object, table }
+            Type::Ref(inner) if matches!(**inner, Type::Interface(_)) => {
+                let i8_ptr = LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0);
+                let mut fields = [i8_ptr, LLVMPointerType(i8_ptr, 0)];
+                LLVMStructTypeInContext(llvm_context, fields.as_mut_ptr(), 2, 0)
+            }
+            // Never used on its own, only behind a pointer
+            Type::Interface(_) => LLVMInt8TypeInContext(llvm_context),
             Type::Ref(inner) => {
                 let inner_type = inner.as_llvm_type(ctx, llvm_context);
                 LLVMPointerType(inner_type, 0)
             }
-            Type::Str => todo!("Write a string struct"),
+            // Function values are pointers to the function
+            Type::Function(params, return_type) => {
+                let mut param_types: Vec<LLVMTypeRef> = params
+                    .iter()
+                    .map(|p| p.as_llvm_type(ctx, llvm_context))
+                    .collect();
+                let return_type = return_type.as_llvm_type(ctx, llvm_context);
+                let func_type = LLVMFunctionType(
+                    return_type,
+                    param_types.as_mut_ptr(),
+                    param_types.len() as c_uint,
+                    0,
+                );
+                LLVMPointerType(func_type, 0)
+            }
+            // { data, len }
+            Type::Str => {
+                let i8_type = LLVMInt8TypeInContext(llvm_context);
+                let mut fields = [
+                    LLVMPointerType(i8_type, 0),
+                    LLVMInt32TypeInContext(llvm_context),
+                ];
+                LLVMStructTypeInContext(llvm_context, fields.as_mut_ptr(), 2, 0)
+            }
             Type::CStr => {
                 let i8_type = LLVMInt8TypeInContext(llvm_context);
                 LLVMPointerType(i8_type, 0)
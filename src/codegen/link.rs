@@ -0,0 +1,29 @@
+use crate::codegen::error::CodegenError;
+
+use std::path::Path;
+use std::process::Command;
+
+/// Link object files into an executable by running `linker` as a C compiler driver.
+pub fn link(objects: &[&Path], output: &Path, linker: &str) -> Result<(), CodegenError> {
+    let result = Command::new(linker)
+        .args(objects)
+        // Externs like `sqrt` live in the C maths library, which is separate on some systems
+        .arg("-lm")
+        .arg("-o")
+        .arg(output)
+        .output();
+
+    match result {
+        Err(e) => Err(CodegenError::LinkFailed(format!(
+            "could not run `{}`: {}",
+            linker, e
+        ))),
+        Ok(out) if !out.status.success() => Err(CodegenError::LinkFailed(format!(
+            "`{}` exited with {}\n{}",
+            linker,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        ))),
+        Ok(_) => Ok(()),
+    }
+}
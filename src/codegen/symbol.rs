@@ -2,8 +2,9 @@ use llvm_sys::prelude::LLVMValueRef;
 use std::collections::HashMap;
 
 use super::CodegenError;
+use crate::type_checker::typed_ast::Type;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SymbolType {
     Const,
     Var,
@@ -21,15 +22,30 @@ impl Symbol {
     }
 }
 
-pub struct ScopedSymbolTable<T> {
+/// A value that must be destroyed when its scope ends.
+pub struct Cleanup {
+    pub ptr: LLVMValueRef,
+    pub ty: Type,
+}
+
+impl Cleanup {
+    pub fn new(ptr: LLVMValueRef, ty: Type) -> Self {
+        Self { ptr, ty }
+    }
+}
+
+/// Each scope holds its names, and the values to clean up (`C`) when it ends.
+pub struct ScopedSymbolTable<T, C = ()> {
     stack: Vec<HashMap<String, T>>,
+    cleanups: Vec<Vec<C>>,
 }
 
-impl<T> ScopedSymbolTable<T> {
+impl<T, C> ScopedSymbolTable<T, C> {
     /// Create a new scoped symbol table with a global scope.
     pub fn new() -> Self {
         Self {
             stack: vec![HashMap::new()],
+            cleanups: vec![vec![]],
         }
     }
 
@@ -47,9 +63,15 @@ impl<T> ScopedSymbolTable<T> {
         Ok(())
     }
 
+    /// Adds a symbol to the global scope, wherever it is used from.
+    pub fn add_global_symbol(&mut self, name: String, value: T) {
+        self.stack[0].insert(name, value);
+    }
+
     /// Add an empty scope to the symbol table.
     pub fn push_scope(&mut self) {
         self.stack.push(HashMap::new());
+        self.cleanups.push(vec![]);
     }
 
     /// Remove the last scope as long as there is more than one scope left.
@@ -59,6 +81,26 @@ impl<T> ScopedSymbolTable<T> {
         }
 
         self.stack.pop();
+        self.cleanups.pop();
         Ok(())
     }
+
+    /// The number of scopes, including the global one.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Register a cleanup for when the last scope ends.
+    pub fn add_cleanup(&mut self, cleanup: C) {
+        self.cleanups.last_mut().unwrap().push(cleanup);
+    }
+
+    /// The cleanups of every scope from `depth` inwards, in the order they
+    /// should run: innermost scope first, latest definition first.
+    pub fn cleanups_from(&self, depth: usize) -> impl Iterator<Item = &C> {
+        self.cleanups[depth.min(self.cleanups.len())..]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+    }
 }
@@ -0,0 +1,132 @@
+use crate::codegen::error::CodegenError;
+
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::sync::Once;
+
+use llvm_sys::core::LLVMDisposeMessage;
+use llvm_sys::target::*;
+use llvm_sys::target_machine::*;
+use llvm_sys::LLVMModule;
+
+static INIT_TARGETS: Once = Once::new();
+
+/// Register every target backend compiled into LLVM. Safe to call more than once.
+pub fn initialize_targets() {
+    INIT_TARGETS.call_once(|| unsafe {
+        LLVM_InitializeAllTargetInfos();
+        LLVM_InitializeAllTargets();
+        LLVM_InitializeAllTargetMCs();
+        LLVM_InitializeAllAsmPrinters();
+        LLVM_InitializeAllAsmParsers();
+    });
+}
+
+/// Owned LLVM target machine used to lower a module to native assembly or objects.
+pub struct TargetMachine {
+    machine: LLVMTargetMachineRef,
+    triple: CString,
+}
+
+impl TargetMachine {
+    /// Create a target machine for `triple`, or for the host if no triple is given.
+    pub unsafe fn new(triple: Option<&str>) -> Result<Self, CodegenError> {
+        initialize_targets();
+
+        let (triple, cpu, features) = match triple {
+            Some(triple) => {
+                let c_triple =
+                    CString::new(triple).map_err(|_| CodegenError::BadTarget(triple.to_owned()))?;
+                (
+                    take_message(LLVMNormalizeTargetTriple(c_triple.as_ptr())),
+                    CString::new("generic").unwrap(),
+                    CString::default(),
+                )
+            }
+            None => (
+                take_message(LLVMGetDefaultTargetTriple()),
+                take_message(LLVMGetHostCPUName()),
+                take_message(LLVMGetHostCPUFeatures()),
+            ),
+        };
+
+        let mut target = std::ptr::null_mut();
+        let mut message = std::ptr::null_mut();
+        if LLVMGetTargetFromTriple(triple.as_ptr(), &mut target, &mut message) != 0 {
+            let err = take_message(message);
+            return Err(CodegenError::BadTarget(format!(
+                "{}: {}",
+                triple.to_string_lossy(),
+                err.to_string_lossy()
+            )));
+        }
+
+        let machine = LLVMCreateTargetMachine(
+            target,
+            triple.as_ptr(),
+            cpu.as_ptr(),
+            features.as_ptr(),
+            LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            // Position independent so the system linker can produce a PIE
+            LLVMRelocMode::LLVMRelocPIC,
+            LLVMCodeModel::LLVMCodeModelDefault,
+        );
+        if machine.is_null() {
+            return Err(CodegenError::BadTarget(
+                triple.to_string_lossy().into_owned(),
+            ));
+        }
+
+        Ok(Self { machine, triple })
+    }
+
+    /// Set the target triple and data layout of `module` to match this machine.
+    pub unsafe fn configure_module(&self, module: *mut LLVMModule) {
+        llvm_sys::core::LLVMSetTarget(module, self.triple.as_ptr());
+        let data_layout = LLVMCreateTargetDataLayout(self.machine);
+        LLVMSetModuleDataLayout(module, data_layout);
+        LLVMDisposeTargetData(data_layout);
+    }
+
+    /// Lower `module` to a native assembly or object file at `path`.
+    pub unsafe fn emit_to_file(
+        &self,
+        module: *mut LLVMModule,
+        path: &Path,
+        file_type: LLVMCodeGenFileType,
+    ) -> Result<(), CodegenError> {
+        let c_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| CodegenError::WriteFailed(format!("invalid path: {}", path.display())))?;
+        let mut message = std::ptr::null_mut();
+        if LLVMTargetMachineEmitToFile(
+            self.machine,
+            module,
+            c_path.as_ptr() as *mut _,
+            file_type,
+            &mut message,
+        ) != 0
+        {
+            let err = take_message(message);
+            return Err(CodegenError::WriteFailed(
+                err.to_string_lossy().into_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeTargetMachine(self.machine);
+        }
+    }
+}
+
+/// Copy a string allocated by LLVM and free the original.
+unsafe fn take_message(message: *mut std::os::raw::c_char) -> CString {
+    let owned = CStr::from_ptr(message).to_owned();
+    LLVMDisposeMessage(message);
+    owned
+}
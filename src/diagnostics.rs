@@ -0,0 +1,256 @@
+use crate::ast::Span;
+use crate::codegen::error::CodegenError;
+use crate::lexer::{self, Token};
+use crate::type_checker::error::TypeCheckerError;
+use crate::type_checker::typed_ast;
+
+use lalrpop_util::ParseError;
+
+/// A compiler error that can be rendered against the source it came from.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+            label: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Build a diagnostic from a LALRPOP error. `src` is used to quote the offending token.
+    pub fn from_parse_error(err: ParseError<usize, Token, lexer::Error>, src: &str) -> Self {
+        match err {
+            ParseError::InvalidToken { location } => {
+                Diagnostic::error("invalid token").with_span(Span::new(location, location + 1))
+            }
+            ParseError::UnrecognizedEOF { location, expected } => {
+                Diagnostic::error("unexpected end of file")
+                    .with_span(Span::new(location, location))
+                    .with_note(expected_one_of(&expected))
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, _, end),
+                expected,
+            } => Diagnostic::error(format!("unexpected `{}`", &src[start..end]))
+                .with_span(Span::new(start, end))
+                .with_label("unexpected token")
+                .with_note(expected_one_of(&expected)),
+            ParseError::ExtraToken {
+                token: (start, _, end),
+            } => Diagnostic::error(format!("unexpected `{}`", &src[start..end]))
+                .with_span(Span::new(start, end))
+                .with_label("expected end of file"),
+            ParseError::User { error } => error.into(),
+        }
+    }
+
+    /// Render in the style of rustc, with the source line and the span underlined.
+    pub fn render(&self, file_name: &str, src: &str) -> String {
+        let mut out = format!("error: {}\n", self.message);
+
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                for note in self.notes.iter() {
+                    out.push_str(&format!("  = note: {}\n", note));
+                }
+                return out;
+            }
+        };
+
+        let (line, col) = span.line_col(src);
+        let line_text = src
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("")
+            .trim_end_matches('\r');
+        let gutter = " ".repeat(line.to_string().len());
+
+        // Only underline the first line of a multi-line span
+        let line_start = src[..span.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let underline_end = span.end.min(line_start + line_text.len());
+        let width = src
+            .get(span.start.min(underline_end)..underline_end)
+            .map(|s| s.chars().count())
+            .unwrap_or(0)
+            .max(1);
+
+        out.push_str(&format!("{}--> {}:{}:{}\n", gutter, file_name, line, col));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", line, line_text));
+        out.push_str(&format!(
+            "{} | {}{}",
+            gutter,
+            " ".repeat(col - 1),
+            "^".repeat(width)
+        ));
+        if let Some(label) = &self.label {
+            out.push_str(&format!(" {}", label));
+        }
+        out.push('\n');
+
+        if !self.notes.is_empty() {
+            out.push_str(&format!("{} |\n", gutter));
+        }
+        for note in self.notes.iter() {
+            out.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+
+        out
+    }
+}
+
+impl From<lexer::Error> for Diagnostic {
+    fn from(err: lexer::Error) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string()).with_span(err.span);
+        match err.kind {
+            lexer::ErrorKind::InvalidEscape(_) => diagnostic.with_note(
+                "valid escapes are `\\n`, `\\r`, `\\t`, `\\0`, `\\\\`, `\\\"` and `\\'`",
+            ),
+            lexer::ErrorKind::ChainedAssignment => {
+                diagnostic.with_note("an assignment has no value, so assign each name on its own")
+            }
+            lexer::ErrorKind::InvalidChar => {
+                diagnostic.with_note("a character literal holds one ASCII character, as in `'a'`")
+            }
+            lexer::ErrorKind::IntOutOfRange => diagnostic.with_note(
+                "the suffix (e.g. `_u8`) decides the type of the literal, and one \
+                     without a suffix must fit in `int64` or `uint64`",
+            ),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<TypeCheckerError> for Diagnostic {
+    fn from(err: TypeCheckerError) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string()).with_span(err.span());
+        match err {
+            TypeCheckerError::InInstance { instance, error } => {
+                Diagnostic::from(*error).with_note(format!("while checking `{}`", instance))
+            }
+            TypeCheckerError::Mismatch { expected, .. } => {
+                diagnostic.with_label(format!("expected `{}`", expected))
+            }
+            TypeCheckerError::AssignToConst(name, _) => diagnostic.with_note(format!(
+                "declare `{}` with `var` to make it assignable",
+                name
+            )),
+            TypeCheckerError::NotIndexable(..) => {
+                diagnostic.with_note("only arrays and pointers can be indexed")
+            }
+            TypeCheckerError::NotConstant(_) => diagnostic.with_note(
+                "global constants may only use literals, operators and other global constants",
+            ),
+            TypeCheckerError::InvalidCast { from, to, .. }
+                if (is_pointer(&from) && to.is_integer()) || (from.is_integer() && is_pointer(&to)) =>
+            {
+                diagnostic.with_note("pointers only convert to and from `int64` and `uint64`")
+            }
+            TypeCheckerError::InvalidCast { .. } => diagnostic
+                .with_note("`as` converts between numbers, chars, bools, pointers and strings"),
+            TypeCheckerError::PrivateMember { class, .. } => diagnostic.with_note(format!(
+                "only methods of `{}` can use it unless it is declared `pub`",
+                class
+            )),
+            TypeCheckerError::InvalidLifecycleMethod(..) => {
+                diagnostic.with_note("`init` and `deinit` cannot be static or virtual")
+            }
+            TypeCheckerError::InvalidOperatorMethod(name, _) => {
+                match name.strip_prefix("operator").unwrap_or_default() {
+                    "==" | "!=" | "<" | "<=" | ">" | ">=" => diagnostic
+                        .with_note("comparison operators must return `bool` or an integer"),
+                    "[]" => diagnostic.with_note(
+                        "`a[i]` calls `a.operator[](i)`, which returns the item or a pointer to it",
+                    ),
+                    op => diagnostic.with_note(format!(
+                        "`a {} b` calls `a.operator{}(b)`, which returns the result",
+                        op, op
+                    )),
+                }
+            }
+            TypeCheckerError::InvalidVarArg(typed_ast::Type::Str, _) => {
+                diagnostic.with_note("C expects a `cstr`, so convert it with `as cstr`")
+            }
+            TypeCheckerError::UnknownLabel(label, _) => diagnostic.with_note(format!(
+                "label a loop around this one with `{}: while (...)` or `{}: for ...`",
+                label, label
+            )),
+            TypeCheckerError::CopiedObject(..) => diagnostic.with_note(
+                "only new objects can be stored, and a local can only be moved out with `return`",
+            ),
+            TypeCheckerError::InstanceTooDeep(..) => diagnostic.with_note(
+                "a generic that uses itself with ever larger type arguments never stops making new instances",
+            ),
+            TypeCheckerError::NotIterable(..) => {
+                diagnostic.with_note("loop over an array, or a range of integers as in `0..n`")
+            }
+            TypeCheckerError::NoInit(class, _) => diagnostic.with_note(format!(
+                "add `func init(self, ...)` to `{}`, or list its fields with `{} {{ ... }}`",
+                class, class
+            )),
+            TypeCheckerError::CannotInfer { name, .. } => diagnostic.with_note(format!(
+                "give the type arguments explicitly, as in `{}::<int>`",
+                name
+            )),
+            TypeCheckerError::UnsizedInterface(name, _) => {
+                diagnostic.with_note(format!("use `*{}` instead", name))
+            }
+            TypeCheckerError::RecursiveClass(name, _) => diagnostic.with_note(format!(
+                "refer to `{}` through a pointer (`*{}`) instead",
+                name, name
+            )),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<CodegenError> for Diagnostic {
+    fn from(err: CodegenError) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string());
+        match err.span() {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+}
+
+fn expected_one_of(expected: &[String]) -> String {
+    let names: Vec<String> = expected
+        .iter()
+        .map(|name| format!("`{}`", name.trim_matches('"')))
+        .collect();
+
+    match names.len() {
+        0 => "no more tokens were expected".to_owned(),
+        1 => format!("expected {}", names[0]),
+        _ => format!("expected one of {}", names.join(", ")),
+    }
+}
+
+fn is_pointer(ty: &typed_ast::Type) -> bool {
+    matches!(ty, typed_ast::Type::Ref(_) | typed_ast::Type::CStr)
+}
@@ -1,4 +1,7 @@
+use crate::ast::Span;
+
 use logos::{Lexer, Logos};
+use std::fmt;
 use std::ops::Range;
 
 #[derive(Logos, Clone, Debug, PartialEq)]
@@ -12,9 +15,30 @@ pub enum Token<'a> {
     #[regex(r#""([^"\\]|\\.)*""#, unescape_string)]
     StringLiteral(String),
 
+    #[regex(r#"'([^'\\]|\\.)'"#, unescape_char_literal)]
+    CharLiteral(u8),
+
     #[token("class")]
     Class,
 
+    #[token("self")]
+    SelfParam,
+
+    #[token("interface")]
+    Interface,
+
+    #[token("pub")]
+    Pub,
+
+    #[token("virtual")]
+    Virtual,
+
+    #[token("override")]
+    Override,
+
+    #[token("operator")]
+    Operator,
+
     #[token("func")]
     Func,
 
@@ -78,6 +102,9 @@ pub enum Token<'a> {
     #[token(":")]
     Colon,
 
+    #[token("::")]
+    DoubleColon,
+
     #[token("==")]
     IsEqual,
 
@@ -105,6 +132,12 @@ pub enum Token<'a> {
     #[token("while")]
     While,
 
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
     #[token("and")]
     And,
 
@@ -141,6 +174,12 @@ pub enum Token<'a> {
     #[token("%")]
     Percent,
 
+    #[token("<<")]
+    ShiftLeft,
+
+    #[token(">>")]
+    ShiftRight,
+
     #[token("+=")]
     AddAssign,
 
@@ -153,6 +192,9 @@ pub enum Token<'a> {
     #[token("/=")]
     DivAssign,
 
+    #[token("%=")]
+    RemAssign,
+
     // #[token("\n")]
     // Newline,
     #[token("var")]
@@ -173,6 +215,15 @@ pub enum Token<'a> {
     #[token(",")]
     Comma,
 
+    #[token(".")]
+    Dot,
+
+    #[token("..")]
+    DotDot,
+
+    #[token("...")]
+    Ellipsis,
+
     #[token("(")]
     LParen,
 
@@ -205,22 +256,23 @@ pub enum Token<'a> {
 
     // #[regex(r#"-?[0-9]+"#)]
     // IntLiteral(&'a str),
-    #[regex(r#"-?[0-9]+"#, |lex| lex.slice().parse())]
-    IntLit(i32),
+    // Number literals have no sign, so `x-1` is a subtraction and `-1` a negation
+    #[regex(r#"[0-9]+"#, parse_int)]
+    IntLit(i128),
 
     #[regex(r#"[0-9]+_u"#, parse_uint)]
     UIntLit(u32),
 
-    #[regex(r#"-?[0-9]+_i8"#, parse_i8)]
+    #[regex(r#"[0-9]+_i8"#, parse_i8)]
     Int8Lit(i8),
 
-    #[regex(r#"-?[0-9]+_i16"#, parse_i16)]
+    #[regex(r#"[0-9]+_i16"#, parse_i16)]
     Int16Lit(i16),
 
-    #[regex(r#"-?[0-9]+_i32"#, parse_i32)]
+    #[regex(r#"[0-9]+_i32"#, parse_i32)]
     Int32Lit(i32),
 
-    #[regex(r#"-?[0-9]+_i64"#, parse_i64)]
+    #[regex(r#"[0-9]+_i64"#, parse_i64)]
     Int64Lit(i64),
 
     #[regex(r#"[0-9]+_u8"#, parse_u8)]
@@ -235,6 +287,11 @@ pub enum Token<'a> {
     #[regex(r#"[0-9]+_u64"#, parse_u64)]
     UInt64Lit(u64),
 
+    // A decimal point needs digits on both sides, so `1.` stays an int and a dot
+    #[regex(r#"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?"#, parse_double)]
+    #[regex(r#"[0-9]+[eE][+-]?[0-9]+"#, parse_double)]
+    DoubleLit(f64),
+
     #[regex(r"[ \t\r\n]+", logos::skip)]
     Whitespace,
 
@@ -242,9 +299,9 @@ pub enum Token<'a> {
     Error,
 }
 
-fn unescape_string<'a>(lex: &mut Lexer<'a, Token<'a>>) -> String {
+fn unescape_string<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Option<String> {
     let mut full = lex.slice();
-    if full.chars().nth(0) == Some('c') {
+    if full.starts_with('c') {
         full = &full[1..];
     }
     let without_quotes = &full[1..full.len() - 1];
@@ -255,22 +312,50 @@ fn unescape_string<'a>(lex: &mut Lexer<'a, Token<'a>>) -> String {
 
     while let Some(c) = chars.next() {
         if c == '\\' {
-            // Parse escaped character
-            // TODO: Handle invalid escapes
-            res.push(match chars.next().unwrap() {
-                'n' => '\n',
-                'r' => '\r',
-                't' => '\t',
-                '\\' => '\\',
-                '"' => '"',
-                other => panic!("Invalid string escape character: {}", other),
-            });
+            // Parse escaped character, invalid escapes become an error token
+            res.push(unescape_char(chars.next()?)?);
         } else {
             res.push(c);
         }
     }
 
-    res
+    Some(res)
+}
+
+/// A `char` is a single byte, so only ASCII characters fit.
+fn unescape_char_literal<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Option<u8> {
+    let slice = lex.slice();
+    let mut chars = slice[1..slice.len() - 1].chars();
+    let c = match chars.next()? {
+        '\\' => unescape_char(chars.next()?)?,
+        c => c,
+    };
+    c.is_ascii().then_some(c as u8)
+}
+
+fn unescape_char(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        _ => None,
+    }
+}
+
+/// Unsuffixed literals take their type from where they are used, so they only
+/// need to fit in some 64-bit integer for now.
+fn parse_int<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<i128, std::num::ParseIntError> {
+    lex.slice().parse::<u64>().map(i128::from)
+}
+
+/// Rejects literals too large for a `double`, instead of rounding them to infinity.
+fn parse_double<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Option<f64> {
+    let value: f64 = lex.slice().parse().ok()?;
+    value.is_finite().then_some(value)
 }
 
 fn parse_i8<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<i8, std::num::ParseIntError> {
@@ -327,17 +412,98 @@ fn parse_u64<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<u64, std::num::ParseI
     slice.parse()
 }
 
-impl<'a> Token<'a> {
-    pub fn to_lalr_triple(
-        (t, r): (Token<'a>, Range<usize>),
-    ) -> Result<(usize, Token, usize), Error> {
+/// Lex `src` into the triples expected by the LALRPOP parser.
+pub fn tokenize<'a>(
+    src: &'a str,
+) -> impl Iterator<Item = Result<(usize, Token<'a>, usize), Error>> {
+    Token::lexer(src).spanned().map(move |(t, r)| {
         if t == Token::Error {
-            Err(Error {})
+            Err(Error::from_slice(&src[r.clone()], r))
         } else {
             Ok((r.start, t, r.end))
         }
-    }
+    })
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Span,
 }
 
 #[derive(Debug)]
-pub struct Error;
+pub enum ErrorKind {
+    InvalidToken,
+    InvalidEscape(char),
+    UnterminatedString,
+    IntOutOfRange,
+    FloatOutOfRange,
+    InvalidChar,
+    /// Raised by the parser, for `a = b = c`
+    ChainedAssignment,
+}
+
+impl Error {
+    /// Work out why the lexer rejected `slice`.
+    fn from_slice(slice: &str, r: Range<usize>) -> Self {
+        let span = Span::new(r.start, r.end);
+        let string = slice.strip_prefix('c').unwrap_or(slice);
+
+        let kind = if let Some(inner) = slice.strip_prefix('\'') {
+            match inner
+                .strip_prefix('\\')
+                .and_then(|rest| rest.chars().next())
+            {
+                Some(escaped) if unescape_char(escaped).is_none() => {
+                    ErrorKind::InvalidEscape(escaped)
+                }
+                _ => ErrorKind::InvalidChar,
+            }
+        } else if string.starts_with('"') {
+            if string.len() < 2 || !string.ends_with('"') {
+                ErrorKind::UnterminatedString
+            } else {
+                let mut chars = string.chars();
+                let mut bad = None;
+                while let Some(c) = chars.next() {
+                    if c == '\\' {
+                        let escaped = chars.next().unwrap_or('\\');
+                        if unescape_char(escaped).is_none() {
+                            bad = Some(escaped);
+                            break;
+                        }
+                    }
+                }
+                bad.map(ErrorKind::InvalidEscape)
+                    .unwrap_or(ErrorKind::InvalidToken)
+            }
+        } else if slice
+            .trim_start_matches('-')
+            .starts_with(|c: char| c.is_ascii_digit())
+        {
+            if slice.contains(['.', 'e', 'E']) {
+                ErrorKind::FloatOutOfRange
+            } else {
+                ErrorKind::IntOutOfRange
+            }
+        } else {
+            ErrorKind::InvalidToken
+        };
+
+        Self { kind, span }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::InvalidToken => write!(f, "invalid token"),
+            ErrorKind::InvalidEscape(c) => write!(f, "invalid escape character `{}`", c),
+            ErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            ErrorKind::IntOutOfRange => write!(f, "integer literal is out of range for its type"),
+            ErrorKind::FloatOutOfRange => write!(f, "float literal is out of range for `double`"),
+            ErrorKind::InvalidChar => write!(f, "invalid character literal"),
+            ErrorKind::ChainedAssignment => write!(f, "assignments cannot be chained"),
+        }
+    }
+}
@@ -1,25 +1,69 @@
+#![allow(clippy::enum_variant_names)]
+
 mod ast;
 mod codegen;
+mod diagnostics;
 mod lexer;
 mod type_checker;
 
 use std::io::Read;
-use std::path::PathBuf;
-use std::process::{exit, Command};
+use std::path::{Path, PathBuf};
+use std::process::exit;
 
 use codegen::context::CompilerContext;
-use lexer::Token;
+use codegen::error::CodegenError;
+use codegen::target::TargetMachine;
+use diagnostics::Diagnostic;
 
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 use log::info;
-use logos::Logos;
 
 use lalrpop_util::lalrpop_mod;
-lalrpop_mod!(pub grammar);
+lalrpop_mod!(#[allow(clippy::all)] pub grammar);
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum EmitKind {
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl EmitKind {
+    /// The path used when no output path is given on the command line.
+    fn default_output(self, src: &Path) -> PathBuf {
+        let stem = src.file_stem().unwrap_or_else(|| "output".as_ref());
+        match self {
+            EmitKind::LlvmIr => PathBuf::from(stem).with_extension("ll"),
+            EmitKind::LlvmBc => PathBuf::from(stem).with_extension("bc"),
+            EmitKind::Asm => PathBuf::from(stem).with_extension("s"),
+            EmitKind::Obj => PathBuf::from(stem).with_extension("o"),
+            EmitKind::Exe => PathBuf::from("a.out"),
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 struct CliArgs {
+    /// Source file to compile
     src: PathBuf,
+
+    /// Path to write the output to
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    /// Stage to stop the pipeline at and the kind of file to write
+    #[clap(long, arg_enum, default_value = "exe")]
+    emit: EmitKind,
+
+    /// Target triple to generate code for [default: host]
+    #[clap(long)]
+    target: Option<String>,
+
+    /// C compiler driver used to link executables
+    #[clap(long, default_value = "cc")]
+    linker: String,
 }
 
 fn main() {
@@ -29,56 +73,89 @@ fn main() {
     // Parse command line args
     info!("parsing command line arguments");
     let args = CliArgs::parse();
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.emit.default_output(&args.src));
 
     // Open source file and read to a string
     info!("reading source file: {}", args.src.display());
-    let mut file = match std::fs::File::open(args.src) {
-        Err(e) => {
-            eprintln!("ERROR: {}", e);
-            exit(1);
-        }
-        Ok(file) => file,
-    };
+    let file_name = args.src.display().to_string();
     let mut src = String::new();
-    file.read_to_string(&mut src).unwrap();
+    if let Err(e) = std::fs::File::open(&args.src).and_then(|mut f| f.read_to_string(&mut src)) {
+        report(
+            Diagnostic::error(format!("could not read `{}`: {}", file_name, e)),
+            &file_name,
+            &src,
+        );
+    }
 
     // Tokenize and parse file
     info!("tokenizing source file");
-    let tokens = Token::lexer(&src).spanned().map(Token::to_lalr_triple);
+    let tokens = lexer::tokenize(&src);
 
     info!("parsing tokenized file");
     let ast = match grammar::ProgramParser::new().parse(tokens) {
-        Err(e) => {
-            eprintln!("PARSER ERROR: {:?}", e);
-            exit(1);
-        }
+        Err(e) => report(Diagnostic::from_parse_error(e, &src), &file_name, &src),
         Ok(ast) => ast,
     };
 
     info!("inferring types for ast");
-    let ast = type_checker::inference::infer_types_pass(ast).unwrap();
+    let ast = match type_checker::inference::infer_types_pass(ast) {
+        Err(e) => report(e.into(), &file_name, &src),
+        Ok(ast) => ast,
+    };
 
-    // Compile to LLVM Bitcode
-    info!("compiling parsed program to llvm bitcode");
-    let mut compiler = CompilerContext::new();
-    unsafe {
-        compiler.compile_to_file(ast, "output.bc").unwrap();
+    info!("creating target machine");
+    let target = match unsafe { TargetMachine::new(args.target.as_deref()) } {
+        Err(e) => report(e.into(), &file_name, &src),
+        Ok(target) => target,
+    };
+
+    // Compile to an LLVM module
+    info!("compiling parsed program to llvm ir");
+    let mut compiler = CompilerContext::new(target);
+    if let Err(e) = unsafe { compiler.compile(ast) } {
+        report(e.into(), &file_name, &src);
     }
 
-    // Use clang to compile LLVM Bitcode to native binary
-    info!("running clang on the generated bitcode");
-    Command::new("clang")
-        .arg("output.bc")
-        .stdout(std::process::Stdio::null())
-        .spawn()
-        .expect("Could not start clang");
+    info!("emitting {:?} to {}", args.emit, output.display());
+    if let Err(e) = emit(&compiler, args.emit, &output, &args.linker) {
+        report(e.into(), &file_name, &src);
+    }
+}
+
+/// Print a diagnostic to stderr and exit with a failure code.
+fn report(diagnostic: Diagnostic, file_name: &str, src: &str) -> ! {
+    eprint!("{}", diagnostic.render(file_name, src));
+    exit(1);
+}
 
-    // TODO: Wait for Clang to finish
+/// Write the compiled module to `output` in the form requested by `kind`.
+fn emit(
+    compiler: &CompilerContext,
+    kind: EmitKind,
+    output: &Path,
+    linker: &str,
+) -> Result<(), CodegenError> {
+    unsafe {
+        match kind {
+            EmitKind::LlvmIr => compiler.write_llvm_ir(output),
+            EmitKind::LlvmBc => compiler.write_bitcode(output),
+            EmitKind::Asm => compiler.write_assembly(output),
+            EmitKind::Obj => compiler.write_object(output),
+            EmitKind::Exe => {
+                let object =
+                    std::env::temp_dir().join(format!("cs-class-{}.o", std::process::id()));
+                compiler.write_object(&object)?;
 
-    // Run the compiled binary
-    // Command::new("./a.out")
-    //     .spawn()
-    //     .expect("Could not execute compiled binary");
+                info!("linking {} with {}", object.display(), linker);
+                let result = codegen::link::link(&[&object], output, linker);
+                let _ = std::fs::remove_file(&object);
+                result
+            }
+        }
+    }
 }
 
 // #[cfg(test)]
@@ -0,0 +1,213 @@
+use super::error::TypeCheckerError;
+use super::typed_ast::{BinOp, Expr, ExprInner, Literal, LiteralInner, Type, UnaryOp};
+use crate::ast::Span;
+
+/// Evaluate a checked expression at compile time. `lookup` gives the value of
+/// a named constant.
+pub fn eval(
+    expr: &Expr,
+    lookup: &mut dyn FnMut(&str, Span) -> Result<Literal, TypeCheckerError>,
+) -> Result<Literal, TypeCheckerError> {
+    let val = match &expr.val {
+        ExprInner::Literal(lit) => return Ok(lit.clone()),
+        ExprInner::Ident(name) => return lookup(name, expr.span),
+        ExprInner::Unary(unary) => {
+            let value = eval(&unary.data, lookup)?;
+            match (unary.op, value.val) {
+                (UnaryOp::Not, LiteralInner::Bool(b)) => LiteralInner::Bool(!b),
+                (UnaryOp::Minus, LiteralInner::Float(v)) => LiteralInner::Float(-v),
+                (UnaryOp::Minus, LiteralInner::Double(v)) => LiteralInner::Double(-v),
+                (UnaryOp::Minus, val) => {
+                    let int = to_i128(&val).ok_or(TypeCheckerError::NotConstant(expr.span))?;
+                    from_i128(-int, &expr.ty).ok_or_else(|| {
+                        TypeCheckerError::ConstOverflow(expr.ty.clone(), expr.span)
+                    })?
+                }
+                _ => return Err(TypeCheckerError::NotConstant(expr.span)),
+            }
+        }
+        ExprInner::Binary(binary) => {
+            let lhs = eval(&binary.lhs, lookup)?;
+            let rhs = eval(&binary.rhs, lookup)?;
+            eval_binary(&lhs.val, binary.op, &rhs.val, &expr.ty, expr.span)?
+        }
+        ExprInner::Cast(cast) => {
+            let value = eval(&cast.original, lookup)?;
+            eval_cast(&value.val, &cast.to_type).ok_or(TypeCheckerError::NotConstant(expr.span))?
+        }
+        _ => return Err(TypeCheckerError::NotConstant(expr.span)),
+    };
+
+    Ok(Literal {
+        ty: expr.ty.clone(),
+        val,
+    })
+}
+
+fn eval_binary(
+    lhs: &LiteralInner,
+    op: BinOp,
+    rhs: &LiteralInner,
+    ty: &Type,
+    span: Span,
+) -> Result<LiteralInner, TypeCheckerError> {
+    if let (LiteralInner::Bool(a), LiteralInner::Bool(b)) = (lhs, rhs) {
+        return Ok(LiteralInner::Bool(match op {
+            BinOp::LogicalAnd => *a && *b,
+            BinOp::LogicalOr => *a || *b,
+            BinOp::Eq => a == b,
+            BinOp::Ne => a != b,
+            _ => return Err(TypeCheckerError::NotConstant(span)),
+        }));
+    }
+
+    if let (Some(a), Some(b)) = (to_f64(lhs), to_f64(rhs)) {
+        return eval_float_binary(a, op, b, ty, span);
+    }
+
+    let (a, b) = match (to_i128(lhs), to_i128(rhs)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(TypeCheckerError::NotConstant(span)),
+    };
+    let result = match op {
+        BinOp::Plus => a + b,
+        BinOp::Minus => a - b,
+        BinOp::Times => a * b,
+        BinOp::Divide | BinOp::Remainder if b == 0 => {
+            return Err(TypeCheckerError::ConstDivideByZero(span))
+        }
+        BinOp::Divide => a / b,
+        BinOp::Remainder => a % b,
+        // Shifts drop bits instead of overflowing, as they do at runtime
+        BinOp::ShiftLeft | BinOp::ShiftRight => {
+            let bits = ty.int_bits().ok_or(TypeCheckerError::NotConstant(span))?;
+            let amount = b.rem_euclid(bits.into()) as u32;
+            let shifted = if op == BinOp::ShiftLeft {
+                a << amount
+            } else {
+                a >> amount
+            };
+            return Ok(from_i128(wrap(shifted, ty, bits), ty).unwrap());
+        }
+        BinOp::Eq => return Ok(LiteralInner::Bool(a == b)),
+        BinOp::Ne => return Ok(LiteralInner::Bool(a != b)),
+        BinOp::Gt => return Ok(LiteralInner::Bool(a > b)),
+        BinOp::Gte => return Ok(LiteralInner::Bool(a >= b)),
+        BinOp::Lt => return Ok(LiteralInner::Bool(a < b)),
+        BinOp::Lte => return Ok(LiteralInner::Bool(a <= b)),
+        _ => return Err(TypeCheckerError::NotConstant(span)),
+    };
+
+    from_i128(result, ty).ok_or_else(|| TypeCheckerError::ConstOverflow(ty.clone(), span))
+}
+
+/// Float arithmetic rounds to `ty` like it does at runtime, and never overflows.
+fn eval_float_binary(
+    a: f64,
+    op: BinOp,
+    b: f64,
+    ty: &Type,
+    span: Span,
+) -> Result<LiteralInner, TypeCheckerError> {
+    let result = match op {
+        BinOp::Plus => a + b,
+        BinOp::Minus => a - b,
+        BinOp::Times => a * b,
+        BinOp::Divide => a / b,
+        BinOp::Remainder => a % b,
+        BinOp::Eq => return Ok(LiteralInner::Bool(a == b)),
+        BinOp::Ne => return Ok(LiteralInner::Bool(a != b)),
+        BinOp::Gt => return Ok(LiteralInner::Bool(a > b)),
+        BinOp::Gte => return Ok(LiteralInner::Bool(a >= b)),
+        BinOp::Lt => return Ok(LiteralInner::Bool(a < b)),
+        BinOp::Lte => return Ok(LiteralInner::Bool(a <= b)),
+        _ => return Err(TypeCheckerError::NotConstant(span)),
+    };
+
+    from_f64(result, ty).ok_or(TypeCheckerError::NotConstant(span))
+}
+
+/// Integer, float and bool casts, with the same wrapping as at runtime.
+/// Floats become integers by rounding towards zero.
+fn eval_cast(val: &LiteralInner, to: &Type) -> Option<LiteralInner> {
+    if let Some(value) = to_f64(val) {
+        return match to {
+            Type::Float | Type::Double => from_f64(value, to),
+            _ => from_i128(value.trunc() as i128, to),
+        };
+    }
+    if to.is_float() {
+        return from_f64(to_i128(val)? as f64, to);
+    }
+
+    let value = match *val {
+        LiteralInner::Bool(b) => b.into(),
+        ref other => to_i128(other)?,
+    };
+
+    match to {
+        Type::Bool => Some(LiteralInner::Bool(value != 0)),
+        Type::Char => Some(LiteralInner::Char(value as u8)),
+        _ => from_i128(wrap(value, to, to.int_bits()?), to),
+    }
+}
+
+fn to_i128(val: &LiteralInner) -> Option<i128> {
+    Some(match *val {
+        LiteralInner::Int(v) => v,
+        LiteralInner::Int32(v) => v.into(),
+        LiteralInner::Int8(v) => v.into(),
+        LiteralInner::Int16(v) => v.into(),
+        LiteralInner::Int64(v) => v.into(),
+        LiteralInner::UInt(v) | LiteralInner::UInt32(v) => v.into(),
+        LiteralInner::UInt8(v) => v.into(),
+        LiteralInner::UInt16(v) => v.into(),
+        LiteralInner::UInt64(v) => v.into(),
+        LiteralInner::Char(v) => v.into(),
+        _ => return None,
+    })
+}
+
+fn to_f64(val: &LiteralInner) -> Option<f64> {
+    Some(match *val {
+        LiteralInner::Float(v) => v.into(),
+        LiteralInner::Double(v) => v,
+        _ => return None,
+    })
+}
+
+fn from_f64(value: f64, ty: &Type) -> Option<LiteralInner> {
+    Some(match ty {
+        Type::Float => LiteralInner::Float(value as f32),
+        Type::Double => LiteralInner::Double(value),
+        _ => return None,
+    })
+}
+
+/// Truncate `value` to `bits` wide, as a signed or unsigned value depending on `ty`.
+fn wrap(value: i128, ty: &Type, bits: u32) -> i128 {
+    let modulus = 1i128 << bits;
+    let value = value.rem_euclid(modulus);
+    if !ty.is_unsigned() && value >= modulus / 2 {
+        value - modulus
+    } else {
+        value
+    }
+}
+
+/// Convert back to a literal of type `ty`, or `None` if it does not fit.
+fn from_i128(value: i128, ty: &Type) -> Option<LiteralInner> {
+    Some(match ty {
+        Type::Int => LiteralInner::Int(i32::try_from(value).ok()?.into()),
+        Type::Int8 => LiteralInner::Int8(value.try_into().ok()?),
+        Type::Int16 => LiteralInner::Int16(value.try_into().ok()?),
+        Type::Int32 => LiteralInner::Int32(value.try_into().ok()?),
+        Type::Int64 => LiteralInner::Int64(value.try_into().ok()?),
+        Type::UInt => LiteralInner::UInt(value.try_into().ok()?),
+        Type::UInt8 => LiteralInner::UInt8(value.try_into().ok()?),
+        Type::UInt16 => LiteralInner::UInt16(value.try_into().ok()?),
+        Type::UInt32 => LiteralInner::UInt32(value.try_into().ok()?),
+        Type::UInt64 => LiteralInner::UInt64(value.try_into().ok()?),
+        _ => return None,
+    })
+}
@@ -0,0 +1,249 @@
+use super::typed_ast::{ClassDef, InterfaceDef, Literal, Type};
+use crate::ast;
+use crate::codegen::symbol::{ScopedSymbolTable, SymbolType};
+
+use std::collections::{HashMap, HashSet};
+
+/// A name visible to the type checker.
+#[derive(Clone)]
+pub struct TypeSymbol {
+    pub ty: Type,
+    pub kind: SymbolType,
+    /// Declared in a function body, so the function owns and destroys its value
+    pub is_local: bool,
+}
+
+impl TypeSymbol {
+    pub fn new(ty: Type, kind: SymbolType) -> Self {
+        Self {
+            ty,
+            kind,
+            is_local: false,
+        }
+    }
+
+    /// A `var` or `const` declared in a function body.
+    pub fn local(ty: Type, kind: SymbolType) -> Self {
+        Self {
+            ty,
+            kind,
+            is_local: true,
+        }
+    }
+}
+
+/// A class or function with type parameters. Its body is checked again for
+/// every instance, with the parameters standing for that instance's types.
+#[derive(Clone)]
+pub enum Generic {
+    Class(ast::ClassDef),
+    Func(ast::FunctionDef),
+}
+
+impl Generic {
+    pub fn name(&self) -> &str {
+        match self {
+            Generic::Class(def) => &def.name,
+            Generic::Func(def) => &def.name,
+        }
+    }
+
+    pub fn span(&self) -> ast::Span {
+        match self {
+            Generic::Class(def) => def.span,
+            Generic::Func(def) => def.span,
+        }
+    }
+
+    pub fn type_params(&self) -> &[String] {
+        match self {
+            Generic::Class(def) => &def.type_params,
+            Generic::Func(def) => &def.type_params,
+        }
+    }
+}
+
+pub struct TypeContext {
+    pub names: ScopedSymbolTable<TypeSymbol>,
+    classes: HashMap<String, ClassDef>,
+    interfaces: HashMap<String, InterfaceDef>,
+    consts: HashMap<String, Literal>,
+    return_type: Option<Type>,
+    class: Option<String>,
+    generics: HashMap<String, Generic>,
+    /// The generic and type arguments of each instance, by the instance's name
+    instances: HashMap<String, (String, Vec<Type>)>,
+    /// Instances whose bodies have not been checked yet, with their depth
+    unchecked: Vec<(String, usize)>,
+    /// How many instances deep the code being checked is, counting the
+    /// instance that first used each one
+    instance_depth: usize,
+    /// What the type parameters stand for in the body being checked
+    type_args: HashMap<String, Type>,
+    /// Externs declared with `...`
+    variadic: HashSet<String>,
+    /// Labels of the loops around the statement being checked, innermost last
+    loops: Vec<Option<String>>,
+    /// Whether the body being checked is an `init` method
+    initializing: bool,
+}
+
+impl TypeContext {
+    pub fn new() -> Self {
+        Self {
+            names: ScopedSymbolTable::new(),
+            classes: HashMap::new(),
+            interfaces: HashMap::new(),
+            consts: HashMap::new(),
+            return_type: None,
+            class: None,
+            generics: HashMap::new(),
+            instances: HashMap::new(),
+            unchecked: vec![],
+            instance_depth: 0,
+            type_args: HashMap::new(),
+            variadic: HashSet::new(),
+            loops: vec![],
+            initializing: false,
+        }
+    }
+
+    pub fn add_class(&mut self, class: ClassDef) {
+        self.classes.insert(class.name.clone(), class);
+    }
+
+    pub fn class(&self, name: &str) -> Option<&ClassDef> {
+        self.classes.get(name)
+    }
+
+    pub fn add_interface(&mut self, interface: InterfaceDef) {
+        self.interfaces.insert(interface.name.clone(), interface);
+    }
+
+    pub fn interface(&self, name: &str) -> Option<&InterfaceDef> {
+        self.interfaces.get(name)
+    }
+
+    pub fn add_variadic(&mut self, name: String) {
+        self.variadic.insert(name);
+    }
+
+    pub fn is_variadic(&self, name: &str) -> bool {
+        self.variadic.contains(name)
+    }
+
+    pub fn add_generic(&mut self, generic: Generic) {
+        self.generics.insert(generic.name().to_owned(), generic);
+    }
+
+    pub fn generic(&self, name: &str) -> Option<&Generic> {
+        self.generics.get(name)
+    }
+
+    /// Record a new instance of `generic`, made from the code being checked.
+    /// Its body is checked later, one level deeper.
+    pub fn add_instance(&mut self, name: String, generic: String, args: Vec<Type>) {
+        self.instances.insert(name.clone(), (generic, args));
+        self.unchecked.push((name, self.instance_depth + 1));
+    }
+
+    pub fn instance(&self, name: &str) -> Option<&(String, Vec<Type>)> {
+        self.instances.get(name)
+    }
+
+    /// Take an instance whose body has not been checked yet.
+    pub fn next_unchecked(&mut self) -> Option<(String, usize)> {
+        self.unchecked.pop()
+    }
+
+    pub fn instance_depth(&self) -> usize {
+        self.instance_depth
+    }
+
+    /// Set how many instances deep the code being checked is, returning the
+    /// previous depth.
+    pub fn replace_instance_depth(&mut self, depth: usize) -> usize {
+        std::mem::replace(&mut self.instance_depth, depth)
+    }
+
+    pub fn type_arg(&self, name: &str) -> Option<&Type> {
+        self.type_args.get(name)
+    }
+
+    /// Set what the type parameters stand for, returning the previous ones.
+    pub fn replace_type_args(&mut self, args: HashMap<String, Type>) -> HashMap<String, Type> {
+        std::mem::replace(&mut self.type_args, args)
+    }
+
+    /// Set whether the method being checked is `init`, whose object starts zeroed.
+    pub fn set_initializing(&mut self, initializing: bool) {
+        self.initializing = initializing;
+    }
+
+    pub fn is_initializing(&self) -> bool {
+        self.initializing
+    }
+
+    /// Whether values of type `ty` have a `deinit` to run, directly or in a field.
+    pub fn needs_drop(&self, ty: &Type) -> bool {
+        ty.needs_drop(&|name| self.class(name))
+    }
+
+    /// Whether `derived` is `base` or inherits from it.
+    pub fn is_subclass(&self, derived: &str, base: &str) -> bool {
+        let mut class = Some(derived);
+        while let Some(name) = class {
+            if name == base {
+                return true;
+            }
+            class = self.class(name).and_then(|def| def.base.as_deref());
+        }
+
+        false
+    }
+
+    /// Record the compile-time value of a global constant.
+    pub fn add_const(&mut self, name: String, value: Literal) {
+        self.consts.insert(name, value);
+    }
+
+    pub fn const_value(&self, name: &str) -> Option<&Literal> {
+        self.consts.get(name)
+    }
+
+    /// Set the return type of the function whose body is being checked.
+    pub fn set_return_type(&mut self, ty: Option<Type>) {
+        self.return_type = ty;
+    }
+
+    pub fn return_type(&self) -> Option<&Type> {
+        self.return_type.as_ref()
+    }
+
+    pub fn push_loop(&mut self, label: Option<String>) {
+        self.loops.push(label);
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loops.pop();
+    }
+
+    /// Whether `break` or `continue` with `label` has a loop to jump out of.
+    pub fn in_loop(&self, label: Option<&str>) -> bool {
+        match label {
+            Some(label) => self.loops.iter().any(|l| l.as_deref() == Some(label)),
+            None => !self.loops.is_empty(),
+        }
+    }
+
+    /// Set the class whose methods are being checked.
+    pub fn set_class(&mut self, class: Option<String>) {
+        self.class = class;
+    }
+
+    /// Whether a member of `owner` can be used here. Private members are only
+    /// visible in the methods of the class that declares them.
+    pub fn can_access(&self, owner: &str, is_pub: bool) -> bool {
+        is_pub || self.class.as_deref() == Some(owner)
+    }
+}
@@ -1,10 +1,376 @@
+use super::typed_ast::{BinOp, Type, UnaryOp};
+use crate::ast::Span;
+
 use std::fmt;
 
 #[derive(Debug)]
-pub enum TypeCheckerError {}
+pub enum TypeCheckerError {
+    UnknownName(String, Span),
+    UnknownClass(String, Span),
+    UnknownField {
+        class: String,
+        field: String,
+        span: Span,
+    },
+    MissingField {
+        class: String,
+        field: String,
+        span: Span,
+    },
+    DuplicateField {
+        class: String,
+        field: String,
+        span: Span,
+    },
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    LiteralOutOfRange(Type, Span),
+    InvalidOperands {
+        op: BinOp,
+        lhs: Type,
+        rhs: Type,
+        span: Span,
+    },
+    InvalidOperand {
+        op: UnaryOp,
+        ty: Type,
+        span: Span,
+    },
+    NotAssignable(Span),
+    AssignToConst(String, Span),
+    NotAddressable(Span),
+    NotIndexable(Type, Span),
+    NotCallable(Type, Span),
+    ArgCount {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    EmptyArray(Span),
+    InvalidLen(Type, Span),
+    NoFields(Type, Span),
+    UnknownMethod {
+        class: String,
+        method: String,
+        span: Span,
+    },
+    StaticOnObject {
+        class: String,
+        method: String,
+        span: Span,
+    },
+    PrivateMember {
+        class: String,
+        member: String,
+        span: Span,
+    },
+    MethodValue {
+        class: String,
+        method: String,
+        span: Span,
+    },
+    InvalidCast {
+        from: Type,
+        to: Type,
+        span: Span,
+    },
+    MissingReturn(String, Span),
+    Redefinition(String, Span),
+    RecursiveClass(String, Span),
+    CyclicInheritance(String, Span),
+    MultipleBases(String, Span),
+    InvalidLifecycleMethod(String, Span),
+    InvalidOperatorMethod(String, Span),
+    VariadicValue(String, Span),
+    InvalidVarArg(Type, Span),
+    NotIterable(Type, Span),
+    /// Instances of a generic keep making new instances, by its name
+    InstanceTooDeep(String, Span),
+    /// Storing a value that has to be destroyed somewhere other than where it was made
+    CopiedObject(Type, Span),
+    /// `break` or `continue` outside of a loop
+    OutsideLoop(String, Span),
+    UnknownLabel(String, Span),
+    NoInit(String, Span),
+    UnsizedInterface(String, Span),
+    MissingMethod {
+        class: String,
+        interface: String,
+        method: String,
+        span: Span,
+    },
+    ImplMismatch {
+        interface: String,
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    MissingOverride(String, Span),
+    NothingToOverride(String, Span),
+    OverrideMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    TypeArgCount {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    CannotInfer {
+        name: String,
+        param: String,
+        span: Span,
+    },
+    GenericBase(String, Span),
+    GenericMethod(String, Span),
+    /// An error in the body of a generic, found while checking one instance of it
+    InInstance {
+        instance: String,
+        error: Box<TypeCheckerError>,
+    },
+    NotConstant(Span),
+    CyclicConst(String, Span),
+    ConstOverflow(Type, Span),
+    ConstDivideByZero(Span),
+}
+
+impl TypeCheckerError {
+    pub fn span(&self) -> Span {
+        use TypeCheckerError::*;
+
+        match self {
+            UnknownName(_, span)
+            | UnknownClass(_, span)
+            | UnknownField { span, .. }
+            | MissingField { span, .. }
+            | DuplicateField { span, .. }
+            | Mismatch { span, .. }
+            | LiteralOutOfRange(_, span)
+            | InvalidOperands { span, .. }
+            | InvalidOperand { span, .. }
+            | NotAssignable(span)
+            | AssignToConst(_, span)
+            | NotAddressable(span)
+            | NotIndexable(_, span)
+            | NotCallable(_, span)
+            | Redefinition(_, span)
+            | RecursiveClass(_, span)
+            | CyclicInheritance(_, span)
+            | MultipleBases(_, span)
+            | InvalidLifecycleMethod(_, span)
+            | InvalidOperatorMethod(_, span)
+            | VariadicValue(_, span)
+            | InvalidVarArg(_, span)
+            | NotIterable(_, span)
+            | InstanceTooDeep(_, span)
+            | CopiedObject(_, span)
+            | OutsideLoop(_, span)
+            | UnknownLabel(_, span)
+            | NoInit(_, span)
+            | UnsizedInterface(_, span)
+            | MissingMethod { span, .. }
+            | ImplMismatch { span, .. }
+            | MissingOverride(_, span)
+            | NothingToOverride(_, span)
+            | OverrideMismatch { span, .. }
+            | TypeArgCount { span, .. }
+            | CannotInfer { span, .. }
+            | GenericBase(_, span)
+            | GenericMethod(_, span)
+            | NotConstant(span)
+            | CyclicConst(_, span)
+            | ConstOverflow(_, span)
+            | ConstDivideByZero(span)
+            | ArgCount { span, .. }
+            | InvalidLen(_, span)
+            | NoFields(_, span)
+            | UnknownMethod { span, .. }
+            | PrivateMember { span, .. }
+            | StaticOnObject { span, .. }
+            | MethodValue { span, .. }
+            | InvalidCast { span, .. }
+            | MissingReturn(_, span)
+            | EmptyArray(span) => *span,
+            InInstance { error, .. } => error.span(),
+        }
+    }
+}
 
 impl fmt::Display for TypeCheckerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "type checker error")
+        use TypeCheckerError::*;
+
+        match self {
+            UnknownName(name, _) => write!(f, "cannot find `{}` in this scope", name),
+            UnknownClass(name, _) => write!(f, "unknown class `{}`", name),
+            UnknownField { class, field, .. } => {
+                write!(f, "class `{}` has no field `{}`", class, field)
+            }
+            MissingField { class, field, .. } => {
+                write!(f, "missing field `{}` in initializer of `{}`", field, class)
+            }
+            DuplicateField { class, field, .. } => {
+                write!(f, "field `{}` is initialized twice in `{}`", field, class)
+            }
+            Mismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            LiteralOutOfRange(ty, _) => write!(f, "literal out of range for `{}`", ty),
+            InvalidOperands { op, lhs, rhs, .. } => {
+                write!(f, "cannot apply `{}` to `{}` and `{}`", op, lhs, rhs)
+            }
+            InvalidOperand { op, ty, .. } => write!(f, "cannot apply `{}` to `{}`", op, ty),
+            NotAssignable(_) => write!(f, "invalid left-hand side of assignment"),
+            AssignToConst(name, _) => write!(f, "cannot assign to constant `{}`", name),
+            NotAddressable(_) => write!(f, "cannot take the address of a temporary value"),
+            NotIndexable(ty, _) => write!(f, "cannot index into a value of type `{}`", ty),
+            NotCallable(ty, _) => write!(f, "cannot call a value of type `{}`", ty),
+            ArgCount {
+                expected, found, ..
+            } => write!(
+                f,
+                "this function takes {} argument(s) but {} were supplied",
+                expected, found
+            ),
+            TypeArgCount {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "`{}` takes {} type argument(s) but {} were supplied",
+                name, expected, found
+            ),
+            CannotInfer { name, param, .. } => {
+                write!(f, "cannot infer type `{}` of `{}`", param, name)
+            }
+            GenericBase(name, _) => {
+                write!(f, "generic class `{}` can only implement interfaces", name)
+            }
+            GenericMethod(name, _) => write!(f, "method `{}` cannot have type parameters", name),
+            InInstance { error, .. } => error.fmt(f),
+            Redefinition(name, _) => write!(f, "`{}` is defined more than once", name),
+            RecursiveClass(name, _) => {
+                write!(f, "class `{}` contains itself and has infinite size", name)
+            }
+            CyclicInheritance(name, _) => write!(f, "class `{}` inherits from itself", name),
+            MultipleBases(name, _) => {
+                write!(f, "class `{}` can only inherit from one class", name)
+            }
+            InvalidLifecycleMethod(name, _) if name == "init" => {
+                write!(f, "`init` must take `self` and return nothing")
+            }
+            InvalidLifecycleMethod(name, _) => {
+                write!(f, "`{}` must take only `self` and return nothing", name)
+            }
+            InvalidOperatorMethod(name, _) => {
+                write!(
+                    f,
+                    "`{}` must take `self` and one argument, and return a value",
+                    name
+                )
+            }
+            VariadicValue(name, _) => {
+                write!(
+                    f,
+                    "`{}` takes extra arguments, so it can only be called",
+                    name
+                )
+            }
+            InvalidVarArg(ty, _) => write!(f, "cannot pass `{}` in place of `...`", ty),
+            NotIterable(ty, _) => write!(f, "cannot loop over `{}`", ty),
+            InstanceTooDeep(name, _) => {
+                write!(f, "instances of `{}` nest too deeply", name)
+            }
+            CopiedObject(ty, _) => {
+                write!(
+                    f,
+                    "cannot copy `{}`, since both copies would be destroyed",
+                    ty
+                )
+            }
+            OutsideLoop(keyword, _) => write!(f, "`{}` outside of a loop", keyword),
+            UnknownLabel(label, _) => write!(f, "no loop labelled `{}` around here", label),
+            NoInit(class, _) => write!(f, "class `{}` has no `init` method", class),
+            UnsizedInterface(name, _) => {
+                write!(f, "interface `{}` can only be used behind a pointer", name)
+            }
+            MissingMethod {
+                class,
+                interface,
+                method,
+                ..
+            } => write!(
+                f,
+                "class `{}` is missing method `{}` of interface `{}`",
+                class, method, interface
+            ),
+            ImplMismatch {
+                interface,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "method has type `{}`, but interface `{}` expects `{}`",
+                found, interface, expected
+            ),
+            MissingOverride(name, _) => write!(
+                f,
+                "`{}` replaces an inherited virtual method and must be marked `override`",
+                name
+            ),
+            NothingToOverride(name, _) => {
+                write!(
+                    f,
+                    "`{}` does not override an inherited virtual method",
+                    name
+                )
+            }
+            OverrideMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "override has type `{}`, but the method it overrides has type `{}`",
+                found, expected
+            ),
+            NotConstant(_) => write!(f, "expression cannot be evaluated at compile time"),
+            CyclicConst(name, _) => write!(f, "the value of `{}` depends on itself", name),
+            ConstOverflow(ty, _) => write!(f, "constant expression overflows `{}`", ty),
+            ConstDivideByZero(_) => write!(f, "division by zero in constant expression"),
+            EmptyArray(_) => write!(f, "cannot infer the type of an empty array"),
+            MissingReturn(name, _) => {
+                write!(f, "function `{}` can end without returning a value", name)
+            }
+            InvalidCast { from, to, .. } => write!(f, "cannot cast `{}` as `{}`", from, to),
+            UnknownMethod { class, method, .. } => {
+                write!(f, "`{}` has no method `{}`", class, method)
+            }
+            PrivateMember { class, member, .. } => {
+                write!(f, "`{}` of class `{}` is private", member, class)
+            }
+            StaticOnObject { class, method, .. } => write!(
+                f,
+                "`{}` is a static function, call it as `{}.{}(...)`",
+                method, class, method
+            ),
+            MethodValue { class, method, .. } => write!(
+                f,
+                "method `{}` must be called, or named as `{}.{}`",
+                method, class, method
+            ),
+            NoFields(ty, _) => write!(f, "`{}` has no fields", ty),
+            InvalidLen(ty, _) => write!(f, "cannot take the length of `{}`", ty),
+        }
     }
 }
@@ -1,366 +1,2557 @@
-use crate::codegen::symbol::ScopedSymbolTable;
+use crate::codegen::symbol::SymbolType;
 
-use super::{error::TypeCheckerError, typed_ast};
+use super::context::{Generic, TypeContext, TypeSymbol};
+use super::{const_eval, error::TypeCheckerError, typed_ast};
 use crate::ast;
 
+use std::collections::{HashMap, HashSet};
+
 pub fn infer_types_pass(program: ast::Program) -> Result<typed_ast::Program, TypeCheckerError> {
     let mut new_program = typed_ast::Program(vec![]);
 
-    let mut names: ScopedSymbolTable<typed_ast::Type> = ScopedSymbolTable::new();
+    let mut ctx = TypeContext::new();
+
+    // Generics are only checked once they are used, for each instance
+    let program = take_generics(program, &mut ctx)?;
+
+    // Every top-level signature is known before any body is checked
+    declare(&program, &mut ctx)?;
+    define_consts(&program, &mut ctx)?;
 
     for stmt in program.0 {
-        new_program.0.push(stmt.to_typed(&mut names));
+        new_program.0.extend(stmt.to_typed(&mut ctx)?);
+    }
+
+    // Checking an instance can use more of them
+    while let Some((instance, depth)) = ctx.next_unchecked() {
+        new_program
+            .0
+            .extend(check_instance(&instance, depth, &mut ctx)?);
     }
 
     Ok(new_program)
 }
 
+/// Move the generic classes and functions out of the program into `ctx`.
+fn take_generics(
+    program: ast::Program,
+    ctx: &mut TypeContext,
+) -> Result<ast::Program, TypeCheckerError> {
+    let mut rest = vec![];
+    for stmt in program.0 {
+        let generic = match stmt {
+            ast::TopLevelStmt::ClassDef(def) if !def.type_params.is_empty() => Generic::Class(def),
+            ast::TopLevelStmt::FunctionDef(def) if !def.type_params.is_empty() => {
+                Generic::Func(def)
+            }
+            stmt => {
+                rest.push(stmt);
+                continue;
+            }
+        };
+
+        if ctx.generic(generic.name()).is_some() {
+            return Err(TypeCheckerError::Redefinition(
+                generic.name().to_owned(),
+                generic.span(),
+            ));
+        }
+        ctx.add_generic(generic);
+    }
+
+    Ok(ast::Program(rest))
+}
+
+/// How deeply instances can use new instances of generics. A generic that
+/// uses itself with ever larger type arguments would never stop otherwise.
+const MAX_INSTANCE_DEPTH: usize = 64;
+
+/// The instance of the generic `name` for `args`. The first time, its fields
+/// and method signatures, or its signature, are resolved.
+fn instantiate(
+    name: &str,
+    args: Vec<typed_ast::Type>,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<String, TypeCheckerError> {
+    let generic = ctx.generic(name).unwrap().clone();
+    if generic.type_params().len() != args.len() {
+        return Err(TypeCheckerError::TypeArgCount {
+            name: name.to_owned(),
+            expected: generic.type_params().len(),
+            found: args.len(),
+            span,
+        });
+    }
+
+    let instance = typed_ast::instance_name(name, &args);
+    if ctx.instance(&instance).is_some() {
+        return Ok(instance);
+    }
+    let depth = ctx.instance_depth() + 1;
+    if depth > MAX_INSTANCE_DEPTH {
+        return Err(TypeCheckerError::InstanceTooDeep(name.to_owned(), span));
+    }
+    ctx.add_instance(instance.clone(), name.to_owned(), args.clone());
+
+    let type_args = generic.type_params().iter().cloned().zip(args).collect();
+    let outer = ctx.replace_type_args(type_args);
+    let outer_depth = ctx.replace_instance_depth(depth);
+    let result = match generic {
+        Generic::Class(def) => define_instance_class(
+            ast::ClassDef {
+                name: instance.clone(),
+                type_params: vec![],
+                ..def
+            },
+            ctx,
+        ),
+        Generic::Func(def) => {
+            signature(&def.params, &def.return_type, def.span, ctx).map(|(params, return_type)| {
+                let symbol =
+                    TypeSymbol::new(function_type(&params, &return_type), SymbolType::Func);
+                ctx.names.add_global_symbol(instance.clone(), symbol);
+            })
+        }
+    };
+    ctx.replace_type_args(outer);
+    ctx.replace_instance_depth(outer_depth);
+
+    result.map_err(|error| match error {
+        // The instances' names get too long to be of use in a note
+        TypeCheckerError::InstanceTooDeep(..) => error,
+        error => TypeCheckerError::InInstance {
+            instance: instance.clone(),
+            error: Box::new(error),
+        },
+    })?;
+    Ok(instance)
+}
+
+/// Resolve the fields and methods of an instance of a generic class.
+fn define_instance_class(
+    def: ast::ClassDef,
+    ctx: &mut TypeContext,
+) -> Result<(), TypeCheckerError> {
+    if def
+        .parents
+        .iter()
+        .any(|parent| ctx.interface(parent).is_none())
+    {
+        return Err(TypeCheckerError::GenericBase(def.name.clone(), def.span));
+    }
+
+    // Registered first so that it can refer to itself
+    ctx.add_class(class_stub(&def.name));
+    let defs = HashMap::from([(def.name.as_str(), &def)]);
+    define_class(
+        &def,
+        &defs,
+        &HashSet::new(),
+        ctx,
+        &mut HashSet::new(),
+        &mut vec![],
+    )?;
+
+    let ty = typed_ast::Type::Class(def.name.clone());
+    if contains_class(&ty, &def.name, ctx, &mut vec![]) {
+        return Err(TypeCheckerError::RecursiveClass(def.name.clone(), def.span));
+    }
+
+    Ok(())
+}
+
+/// Check the body of an instance, with its type parameters standing for its type arguments.
+fn check_instance(
+    instance: &str,
+    depth: usize,
+    ctx: &mut TypeContext,
+) -> Result<Vec<typed_ast::TopLevelStmt>, TypeCheckerError> {
+    let (name, args) = ctx.instance(instance).unwrap().clone();
+    let generic = ctx.generic(&name).unwrap().clone();
+    let type_args = generic.type_params().iter().cloned().zip(args).collect();
+
+    let stmt = match generic {
+        Generic::Class(def) => ast::TopLevelStmt::ClassDef(ast::ClassDef {
+            name: instance.to_owned(),
+            type_params: vec![],
+            ..def
+        }),
+        Generic::Func(def) => ast::TopLevelStmt::FunctionDef(ast::FunctionDef {
+            name: instance.to_owned(),
+            type_params: vec![],
+            ..def
+        }),
+    };
+
+    let outer = ctx.replace_type_args(type_args);
+    let outer_depth = ctx.replace_instance_depth(depth);
+    let result = stmt.to_typed(ctx);
+    ctx.replace_type_args(outer);
+    ctx.replace_instance_depth(outer_depth);
+
+    result.map_err(|error| match error {
+        TypeCheckerError::InstanceTooDeep(..) => error,
+        error => TypeCheckerError::InInstance {
+            instance: instance.to_owned(),
+            error: Box::new(error),
+        },
+    })
+}
+
+/// Work out the type arguments of the generic `name` from the types of the
+/// values given for its parameters.
+fn infer_type_args<'a>(
+    name: &str,
+    pairs: impl IntoIterator<Item = (&'a ast::Type, &'a typed_ast::Type)>,
+    span: ast::Span,
+    ctx: &TypeContext,
+) -> Result<Vec<typed_ast::Type>, TypeCheckerError> {
+    let type_params = ctx.generic(name).unwrap().type_params();
+    let mut found = HashMap::new();
+    for (param, arg) in pairs {
+        match_type_params(param, arg, type_params, &mut found, ctx);
+    }
+
+    type_params
+        .iter()
+        .map(|param| {
+            found
+                .remove(param)
+                .ok_or_else(|| TypeCheckerError::CannotInfer {
+                    name: name.to_owned(),
+                    param: param.clone(),
+                    span,
+                })
+        })
+        .collect()
+}
+
+/// Match the parameter type `param` against the argument type `arg`, noting
+/// what each type parameter must be. The first match wins; a conflicting one
+/// is reported as a mismatch when the argument is passed.
+fn match_type_params(
+    param: &ast::Type,
+    arg: &typed_ast::Type,
+    type_params: &[String],
+    found: &mut HashMap<String, typed_ast::Type>,
+    ctx: &TypeContext,
+) {
+    match (param, arg) {
+        (ast::Type::Class(name), _) if type_params.contains(name) => {
+            found.entry(name.clone()).or_insert_with(|| arg.clone());
+        }
+        (ast::Type::Array(param, _), typed_ast::Type::Array(arg, _))
+        | (ast::Type::Ref(param), typed_ast::Type::Ref(arg)) => {
+            match_type_params(param, arg, type_params, found, ctx)
+        }
+        (ast::Type::Function(params, return_type), typed_ast::Type::Function(args, arg_return)) => {
+            for (param, arg) in params.iter().zip(args.iter()) {
+                match_type_params(param, arg, type_params, found, ctx);
+            }
+            if let Some(return_type) = return_type {
+                match_type_params(return_type, arg_return, type_params, found, ctx);
+            }
+        }
+        (ast::Type::Instance(name, params), typed_ast::Type::Class(class)) => {
+            if let Some((generic, args)) = ctx.instance(class) {
+                if generic == name {
+                    for (param, arg) in params.iter().zip(args.iter()) {
+                        match_type_params(param, arg, type_params, found, ctx);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Register every class, interface, function, extern and global constant in the program.
+fn declare(program: &ast::Program, ctx: &mut TypeContext) -> Result<(), TypeCheckerError> {
+    use ast::TopLevelStmt;
+
+    // Classes and interfaces are registered empty first so that fields and
+    // methods can name any of them
+    for stmt in program.0.iter() {
+        let (name, span) = match stmt {
+            TopLevelStmt::ClassDef(def) => (&def.name, def.span),
+            TopLevelStmt::InterfaceDef(def) => (&def.name, def.span),
+            _ => continue,
+        };
+        if ctx.class(name).is_some() || ctx.interface(name).is_some() || ctx.generic(name).is_some()
+        {
+            return Err(TypeCheckerError::Redefinition(name.clone(), span));
+        }
+
+        match stmt {
+            TopLevelStmt::ClassDef(_) => ctx.add_class(class_stub(name)),
+            _ => ctx.add_interface(typed_ast::InterfaceDef {
+                name: name.clone(),
+                methods: vec![],
+            }),
+        }
+    }
+
+    for stmt in program.0.iter() {
+        if let TopLevelStmt::InterfaceDef(def) = stmt {
+            define_interface(def, ctx)?;
+        }
+    }
+
+    let classes: Vec<&ast::ClassDef> = program
+        .0
+        .iter()
+        .filter_map(|stmt| match stmt {
+            TopLevelStmt::ClassDef(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+    let defs: HashMap<&str, &ast::ClassDef> = classes
+        .iter()
+        .map(|def| (def.name.as_str(), *def))
+        .collect();
+    let bases: HashSet<&str> = classes
+        .iter()
+        .flat_map(|def| def.parents.iter())
+        .map(String::as_str)
+        .filter(|name| defs.contains_key(name))
+        .collect();
+
+    let mut defined = HashSet::new();
+    for def in classes.iter() {
+        define_class(def, &defs, &bases, ctx, &mut defined, &mut vec![])?;
+    }
+
+    for stmt in program.0.iter() {
+        if let TopLevelStmt::ClassDef(def) = stmt {
+            if contains_class(
+                &typed_ast::Type::Class(def.name.clone()),
+                &def.name,
+                ctx,
+                &mut vec![],
+            ) {
+                return Err(TypeCheckerError::RecursiveClass(def.name.clone(), def.span));
+            }
+        }
+    }
+
+    for stmt in program.0.iter() {
+        let (name, symbol, span) = match stmt {
+            TopLevelStmt::ClassDef(_) | TopLevelStmt::InterfaceDef(_) => continue,
+            TopLevelStmt::FunctionDef(def) => {
+                let (params, return_type) =
+                    signature(&def.params, &def.return_type, def.span, ctx)?;
+                let ty = function_type(&params, &return_type);
+                (&def.name, TypeSymbol::new(ty, SymbolType::Func), def.span)
+            }
+            TopLevelStmt::ExternDef(def) => {
+                let (params, return_type) =
+                    signature(&def.params, &def.return_type, def.span, ctx)?;
+                let ty = function_type(&params, &return_type);
+                if def.is_variadic {
+                    ctx.add_variadic(def.name.clone());
+                }
+                (&def.name, TypeSymbol::new(ty, SymbolType::Func), def.span)
+            }
+            TopLevelStmt::ConstDef(def) => {
+                let binding = def.binding.clone().to_typed(ctx)?;
+                (
+                    &def.binding.name,
+                    TypeSymbol::new(binding.ty, SymbolType::Const),
+                    def.span,
+                )
+            }
+        };
+
+        if ctx.names.get_symbol(name).is_some() || ctx.generic(name).is_some() {
+            return Err(TypeCheckerError::Redefinition(name.clone(), span));
+        }
+        ctx.names.add_symbol(name.clone(), symbol).unwrap();
+    }
+
+    Ok(())
+}
+
+/// A class with nothing resolved yet.
+fn class_stub(name: &str) -> typed_ast::ClassDef {
+    typed_ast::ClassDef {
+        name: name.to_owned(),
+        base: None,
+        interfaces: vec![],
+        fields: vec![],
+        methods: vec![],
+        has_vtable: false,
+    }
+}
+
+/// Resolve the fields and methods of a class, after those of its base.
+fn define_class(
+    def: &ast::ClassDef,
+    defs: &HashMap<&str, &ast::ClassDef>,
+    bases: &HashSet<&str>,
+    ctx: &mut TypeContext,
+    defined: &mut HashSet<String>,
+    in_progress: &mut Vec<String>,
+) -> Result<(), TypeCheckerError> {
+    if defined.contains(&def.name) {
+        return Ok(());
+    }
+    if in_progress.contains(&def.name) {
+        return Err(TypeCheckerError::CyclicInheritance(
+            def.name.clone(),
+            def.span,
+        ));
+    }
+    in_progress.push(def.name.clone());
+
+    let mut base: Option<&String> = None;
+    let mut own_interfaces: Vec<String> = vec![];
+    for parent in def.parents.iter() {
+        if ctx.interface(parent).is_some() {
+            own_interfaces.push(parent.clone());
+        } else if !defs.contains_key(parent.as_str()) {
+            return Err(TypeCheckerError::UnknownClass(parent.clone(), def.span));
+        } else if base.is_some() {
+            return Err(TypeCheckerError::MultipleBases(def.name.clone(), def.span));
+        } else {
+            base = Some(parent);
+        }
+    }
+
+    let (mut fields, mut methods, mut interfaces) = match base {
+        Some(base) => {
+            define_class(defs[base.as_str()], defs, bases, ctx, defined, in_progress)?;
+            let base_def = ctx.class(base).unwrap();
+            (
+                base_def.fields.clone(),
+                base_def.methods.clone(),
+                base_def.interfaces.clone(),
+            )
+        }
+        None => (vec![], vec![], vec![]),
+    };
+    // Every class sets up and tears down its own fields
+    methods.retain(|m| m.name != typed_ast::INIT && m.name != typed_ast::DEINIT);
+    let mut next_slot = methods.iter().filter(|m| m.slot.is_some()).count();
+
+    for field in def.fields.iter() {
+        if fields
+            .iter()
+            .any(|f: &typed_ast::Field| f.name == field.name)
+        {
+            return Err(TypeCheckerError::Redefinition(
+                field.name.clone(),
+                field.span,
+            ));
+        }
+        fields.push(typed_ast::Field {
+            name: field.name.clone(),
+            ty: check_type(field.ty.clone(), ctx, field.span)?,
+            owner: def.name.clone(),
+            is_pub: field.is_pub,
+        });
+    }
+
+    for method in def.methods.iter() {
+        let name = &method.func.name;
+        let span = method.func.span;
+        let inherited = methods.iter().position(|m| m.name == *name);
+        if fields.iter().any(|f| f.name == *name)
+            || inherited.is_some_and(|i| methods[i].owner == def.name)
+        {
+            return Err(TypeCheckerError::Redefinition(name.clone(), span));
+        }
+
+        if !method.func.type_params.is_empty() {
+            return Err(TypeCheckerError::GenericMethod(name.clone(), span));
+        }
+        let params = method_params(&def.name, method);
+        let (params, return_type) = signature(&params, &method.func.return_type, span, ctx)?;
+        let ty = function_type(&params, &return_type);
+
+        let is_lifecycle = *name == typed_ast::INIT || *name == typed_ast::DEINIT;
+        if is_lifecycle
+            && (method.is_static
+                || method.is_virtual
+                || return_type != typed_ast::Type::NoneType
+                || (*name == typed_ast::DEINIT && params.len() != 1))
+        {
+            return Err(TypeCheckerError::InvalidLifecycleMethod(name.clone(), span));
+        }
+
+        // Operators are only ever called with the object and one operand
+        let valid_operator = match name.strip_prefix("operator") {
+            Some("==" | "!=" | "<" | "<=" | ">" | ">=") => {
+                Some(return_type == typed_ast::Type::Bool || return_type.is_integer())
+            }
+            Some("+" | "-" | "*" | "/" | "%" | "[]") => {
+                Some(return_type != typed_ast::Type::NoneType)
+            }
+            _ => None,
+        };
+        if valid_operator.is_some_and(|valid| !valid || params.len() != 2) {
+            return Err(TypeCheckerError::InvalidOperatorMethod(name.clone(), span));
+        }
+
+        let slot = match inherited.map(|i| &methods[i]) {
+            Some(base) if base.slot.is_some() => {
+                if !method.is_override {
+                    return Err(TypeCheckerError::MissingOverride(name.clone(), span));
+                }
+                if !same_signature(&base.ty, &ty) {
+                    return Err(TypeCheckerError::OverrideMismatch {
+                        expected: base.ty.clone(),
+                        found: ty,
+                        span,
+                    });
+                }
+                base.slot
+            }
+            _ if method.is_override => {
+                return Err(TypeCheckerError::NothingToOverride(name.clone(), span))
+            }
+            Some(_) => return Err(TypeCheckerError::Redefinition(name.clone(), span)),
+            None if method.is_virtual => {
+                next_slot += 1;
+                Some(next_slot - 1)
+            }
+            None => None,
+        };
+
+        // Instances can be defined while a function body is being checked
+        ctx.names.add_global_symbol(
+            typed_ast::method_symbol(&def.name, name),
+            TypeSymbol::new(ty.clone(), SymbolType::Func),
+        );
+        let method = typed_ast::Method {
+            name: name.clone(),
+            owner: def.name.clone(),
+            is_pub: method.is_pub,
+            is_static: method.is_static,
+            slot,
+            ty,
+            span,
+        };
+        match inherited {
+            Some(i) => methods[i] = method,
+            None => methods.push(method),
+        }
+    }
+
+    for interface in own_interfaces {
+        check_implements(&def.name, &methods, &interface, def.span, ctx)?;
+        if !interfaces.contains(&interface) {
+            interfaces.push(interface);
+        }
+    }
+
+    let has_vtable = base.is_some()
+        || bases.contains(def.name.as_str())
+        || methods.iter().any(|m| m.slot.is_some());
+    ctx.add_class(typed_ast::ClassDef {
+        name: def.name.clone(),
+        base: base.cloned(),
+        interfaces,
+        fields,
+        methods,
+        has_vtable,
+    });
+
+    in_progress.pop();
+    defined.insert(def.name.clone());
+    Ok(())
+}
+
+/// Check that a class with `methods` has every method of `interface`.
+fn check_implements(
+    class: &str,
+    methods: &[typed_ast::Method],
+    interface: &str,
+    span: ast::Span,
+    ctx: &TypeContext,
+) -> Result<(), TypeCheckerError> {
+    for required in ctx.interface(interface).unwrap().methods.iter() {
+        let method = methods
+            .iter()
+            .find(|m| m.name == required.name && !m.is_static)
+            .ok_or_else(|| TypeCheckerError::MissingMethod {
+                class: class.to_owned(),
+                interface: interface.to_owned(),
+                method: required.name.clone(),
+                span,
+            })?;
+        // Anyone holding the interface can call it
+        if !method.is_pub {
+            return Err(TypeCheckerError::PrivateMember {
+                class: method.owner.clone(),
+                member: method.name.clone(),
+                span: method.span,
+            });
+        }
+        if !same_signature(&required.ty, &method.ty) {
+            return Err(TypeCheckerError::ImplMismatch {
+                interface: interface.to_owned(),
+                expected: required.ty.clone(),
+                found: method.ty.clone(),
+                span: method.span,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve the method signatures of an interface.
+fn define_interface(
+    def: &ast::InterfaceDef,
+    ctx: &mut TypeContext,
+) -> Result<(), TypeCheckerError> {
+    let mut methods: Vec<typed_ast::Method> = vec![];
+    for (slot, method) in def.methods.iter().enumerate() {
+        if methods.iter().any(|m| m.name == method.name) {
+            return Err(TypeCheckerError::Redefinition(
+                method.name.clone(),
+                method.span,
+            ));
+        }
+
+        let mut params = method.params.clone();
+        params.insert(
+            0,
+            ast::TypeBinding {
+                name: "self".to_owned(),
+                ty: ast::Type::Ref(Box::new(ast::Type::Class(def.name.clone()))),
+                span: method.span,
+            },
+        );
+        let (params, return_type) = signature(&params, &method.return_type, method.span, ctx)?;
+        methods.push(typed_ast::Method {
+            name: method.name.clone(),
+            owner: def.name.clone(),
+            is_pub: true,
+            is_static: false,
+            slot: Some(slot),
+            ty: function_type(&params, &return_type),
+            span: method.span,
+        });
+    }
+
+    ctx.add_interface(typed_ast::InterfaceDef {
+        name: def.name.clone(),
+        methods,
+    });
+    Ok(())
+}
+
+/// Whether two methods take the same arguments after `self` and return the same type.
+fn same_signature(a: &typed_ast::Type, b: &typed_ast::Type) -> bool {
+    match (a, b) {
+        (
+            typed_ast::Type::Function(a_params, a_return),
+            typed_ast::Type::Function(b_params, b_return),
+        ) => a_params.get(1..) == b_params.get(1..) && a_return == b_return,
+        _ => false,
+    }
+}
+
+/// Evaluate every global constant. Constants may refer to each other in any order.
+fn define_consts(program: &ast::Program, ctx: &mut TypeContext) -> Result<(), TypeCheckerError> {
+    let defs: HashMap<&str, &ast::GlobalConstDef> = program
+        .0
+        .iter()
+        .filter_map(|stmt| match stmt {
+            ast::TopLevelStmt::ConstDef(def) => Some((def.binding.name.as_str(), def)),
+            _ => None,
+        })
+        .collect();
+
+    for stmt in program.0.iter() {
+        if let ast::TopLevelStmt::ConstDef(def) = stmt {
+            eval_global(def, &defs, ctx, &mut vec![])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn eval_global(
+    def: &ast::GlobalConstDef,
+    defs: &HashMap<&str, &ast::GlobalConstDef>,
+    ctx: &mut TypeContext,
+    in_progress: &mut Vec<String>,
+) -> Result<typed_ast::Literal, TypeCheckerError> {
+    let name = &def.binding.name;
+    if let Some(value) = ctx.const_value(name) {
+        return Ok(value.clone());
+    }
+    if in_progress.contains(name) {
+        return Err(TypeCheckerError::CyclicConst(name.clone(), def.span));
+    }
+    in_progress.push(name.clone());
+
+    let ty = ctx.names.get_symbol(name).unwrap().ty.clone();
+    let value = coerce(def.value.clone().to_typed(ctx)?, &ty, ctx)?;
+    let literal = const_eval::eval(&value, &mut |ident, span| match defs.get(ident) {
+        Some(other) => eval_global(other, defs, ctx, in_progress),
+        None => Err(TypeCheckerError::NotConstant(span)),
+    })?;
+
+    in_progress.pop();
+    ctx.add_const(name.clone(), literal.clone());
+    Ok(literal)
+}
+
+/// The parameters of a method's function, with `self` added to the front.
+fn method_params(class: &str, method: &ast::MethodDef) -> Vec<ast::TypeBinding> {
+    let mut params = method.func.params.clone();
+    if !method.is_static {
+        params.insert(
+            0,
+            ast::TypeBinding {
+                name: "self".to_owned(),
+                ty: ast::Type::Ref(Box::new(ast::Type::Class(class.to_owned()))),
+                span: method.func.span,
+            },
+        );
+    }
+
+    params
+}
+
+/// Check the parameter and return types of a function.
+fn signature(
+    params: &[ast::TypeBinding],
+    return_type: &Option<ast::Type>,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<(Vec<typed_ast::TypeBinding>, typed_ast::Type), TypeCheckerError> {
+    let params = params
+        .iter()
+        .map(|binding| binding.clone().to_typed(ctx))
+        .collect::<Result<_, _>>()?;
+    let return_type = match return_type {
+        Some(ty) => check_type(ty.clone(), ctx, span)?,
+        None => typed_ast::Type::NoneType,
+    };
+
+    Ok((params, return_type))
+}
+
+/// Whether a value of type `ty` holds a `class` by value, which would make it infinitely large.
+fn contains_class(
+    ty: &typed_ast::Type,
+    class: &str,
+    ctx: &TypeContext,
+    seen: &mut Vec<String>,
+) -> bool {
+    match ty {
+        typed_ast::Type::Array(inner, _) => contains_class(inner, class, ctx, seen),
+        typed_ast::Type::Class(name) => {
+            if seen.contains(name) {
+                return false;
+            }
+            seen.push(name.clone());
+            ctx.class(name).is_some_and(|def| {
+                def.fields
+                    .iter()
+                    .any(|f| class_name_is(&f.ty, class) || contains_class(&f.ty, class, ctx, seen))
+            })
+        }
+        _ => false,
+    }
+}
+
+fn class_name_is(ty: &typed_ast::Type, class: &str) -> bool {
+    match ty {
+        typed_ast::Type::Array(inner, _) => class_name_is(inner, class),
+        typed_ast::Type::Class(name) => name == class,
+        _ => false,
+    }
+}
+
 trait ToTyped {
     type Typed;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed;
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError>;
 }
 
 impl ToTyped for ast::TopLevelStmt {
-    type Typed = typed_ast::TopLevelStmt;
+    /// Classes expand to the class and one function per method.
+    type Typed = Vec<typed_ast::TopLevelStmt>;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         use ast::TopLevelStmt;
 
-        match self {
-            TopLevelStmt::ClassDef(def) => typed_ast::TopLevelStmt::ClassDef(def.to_typed(names)),
+        Ok(match self {
+            TopLevelStmt::ClassDef(def) => {
+                let (class, methods) = def.to_typed(ctx)?;
+                std::iter::once(typed_ast::TopLevelStmt::ClassDef(class))
+                    .chain(
+                        methods
+                            .into_iter()
+                            .map(typed_ast::TopLevelStmt::FunctionDef),
+                    )
+                    .collect()
+            }
+            TopLevelStmt::InterfaceDef(def) => {
+                // Everything was resolved when the interface was declared
+                let interface = ctx.interface(&def.name).unwrap().clone();
+                vec![typed_ast::TopLevelStmt::InterfaceDef(interface)]
+            }
             TopLevelStmt::FunctionDef(def) => {
-                typed_ast::TopLevelStmt::FunctionDef(def.to_typed(names))
+                vec![typed_ast::TopLevelStmt::FunctionDef(def.to_typed(ctx)?)]
             }
-            TopLevelStmt::ExternDef(def) => typed_ast::TopLevelStmt::ExternDef(def.to_typed(names)),
-            TopLevelStmt::ConstDef(def) => typed_ast::TopLevelStmt::ConstDef(def.to_typed(names)),
-        }
+            TopLevelStmt::ExternDef(def) => {
+                vec![typed_ast::TopLevelStmt::ExternDef(def.to_typed(ctx)?)]
+            }
+            TopLevelStmt::ConstDef(def) => {
+                vec![typed_ast::TopLevelStmt::ConstDef(def.to_typed(ctx)?)]
+            }
+        })
     }
 }
 
 impl ToTyped for ast::ClassDef {
-    type Typed = typed_ast::ClassDef;
+    type Typed = (typed_ast::ClassDef, Vec<typed_ast::FunctionDef>);
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        let fields = self
-            .fields
-            .into_iter()
-            .map(|(name, ty)| (name, ty.to_typed(names)))
-            .collect();
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        // Fields and method signatures were resolved when the class was declared
+        let class = ctx.class(&self.name).unwrap().clone();
 
-        names
-            .add_symbol(self.name.clone(), typed_ast::Type::Class(self.name.clone()))
-            .unwrap();
+        ctx.set_class(Some(self.name.clone()));
+        let methods = self
+            .methods
+            .into_iter()
+            .map(|method| {
+                let params = method_params(&self.name, &method);
+                ctx.set_initializing(method.func.name == typed_ast::INIT);
+                ast::FunctionDef {
+                    name: typed_ast::method_symbol(&self.name, &method.func.name),
+                    params,
+                    ..method.func
+                }
+                .to_typed(ctx)
+            })
+            .collect::<Result<_, _>>();
+        ctx.set_initializing(false);
+        ctx.set_class(None);
+        let methods = methods?;
 
-        typed_ast::ClassDef {
-            name: self.name,
-            fields,
-        }
+        Ok((class, methods))
     }
 }
 
 impl ToTyped for ast::FunctionDef {
     type Typed = typed_ast::FunctionDef;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         let name = self.name;
-        let params: Vec<typed_ast::TypeBinding> = self
-            .params
-            .into_iter()
-            .map(|binding| binding.to_typed(names))
-            .collect();
-        let return_type = self
-            .return_type
-            .map(|t| t.to_typed(names))
-            .unwrap_or(typed_ast::Type::NoneType);
-
-        names.add_symbol(name.clone(), return_type.clone()).unwrap();
+        let (params, return_type) = signature(&self.params, &self.return_type, self.span, ctx)?;
 
-        names.push_scope();
+        ctx.names.push_scope();
+        ctx.set_return_type(Some(return_type.clone()));
         for param in params.iter() {
-            names
-                .add_symbol(param.name.clone(), param.ty.clone())
+            ctx.names
+                .add_symbol(
+                    param.name.clone(),
+                    TypeSymbol::new(param.ty.clone(), SymbolType::Const),
+                )
                 .unwrap();
         }
-        let body = self.body.to_typed(names);
-        names.pop_scope().unwrap();
+        let body = self.body.to_typed(ctx)?;
+        ctx.set_return_type(None);
+        ctx.names.pop_scope().unwrap();
 
-        typed_ast::FunctionDef {
+        if return_type != typed_ast::Type::NoneType && !always_returns(&body) {
+            return Err(TypeCheckerError::MissingReturn(name, self.span));
+        }
+
+        Ok(typed_ast::FunctionDef {
             name,
             params,
             body,
             return_type,
-        }
+        })
     }
 }
 
 impl ToTyped for ast::ExternDef {
     type Typed = typed_ast::ExternDef;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        let return_type = self
-            .return_type
-            .map(|t| t.to_typed(names))
-            .unwrap_or(typed_ast::Type::NoneType);
-
-        names
-            .add_symbol(self.name.clone(), return_type.clone())
-            .unwrap();
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let (params, return_type) = signature(&self.params, &self.return_type, self.span, ctx)?;
 
-        typed_ast::ExternDef {
+        Ok(typed_ast::ExternDef {
             name: self.name,
-            params: self
-                .params
-                .into_iter()
-                .map(|binding| binding.to_typed(names))
-                .collect(),
-            return_type: return_type,
-        }
+            params,
+            is_variadic: self.is_variadic,
+            return_type,
+        })
     }
 }
 
 impl ToTyped for ast::GlobalConstDef {
     type Typed = typed_ast::GlobalConstDef;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        let new_binding = self.binding.to_typed(names);
-        names
-            .add_symbol(new_binding.name.clone(), new_binding.ty.clone())
-            .unwrap();
-        typed_ast::GlobalConstDef {
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let new_binding = self.binding.to_typed(ctx)?;
+        // The value was worked out by `define_consts`
+        let value = ctx.const_value(&new_binding.name).unwrap().clone();
+
+        Ok(typed_ast::GlobalConstDef {
             binding: new_binding,
-            value: self.value.to_typed(names),
-        }
+            value,
+        })
     }
 }
 
 impl ToTyped for ast::Stmt {
     type Typed = typed_ast::Stmt;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         use ast::Stmt;
-        match self {
-            Stmt::BlockStmt(stmts) => typed_ast::Stmt::BlockStmt(stmts.to_typed(names)),
-            Stmt::ExprStmt(expr) => typed_ast::Stmt::ExprStmt(expr.to_typed(names)),
-            Stmt::ConstDef(def) => typed_ast::Stmt::ConstDef(def.to_typed(names)),
-            Stmt::VarDef(def) => typed_ast::Stmt::VarDef(def.to_typed(names)),
-            Stmt::ReturnStmt(expr) => typed_ast::Stmt::ReturnStmt(expr.to_typed(names)),
-            Stmt::WhileStmt(stmt) => typed_ast::Stmt::WhileStmt(stmt.to_typed(names)),
-            Stmt::IfStmt(stmt) => typed_ast::Stmt::IfStmt(stmt.to_typed(names)),
+        Ok(match self {
+            Stmt::BlockStmt(stmts) => typed_ast::Stmt::BlockStmt(stmts.to_typed(ctx)?),
+            Stmt::ExprStmt(expr) => typed_ast::Stmt::ExprStmt(expr.to_typed(ctx)?),
+            Stmt::ConstDef(def) => typed_ast::Stmt::ConstDef(def.to_typed(ctx)?),
+            Stmt::VarDef(def) => typed_ast::Stmt::VarDef(def.to_typed(ctx)?),
+            Stmt::ReturnStmt(stmt) => {
+                let return_type = ctx
+                    .return_type()
+                    .cloned()
+                    .unwrap_or(typed_ast::Type::NoneType);
+                let value = coerce(stmt.value.to_typed(ctx)?, &return_type, ctx)?;
+                // Returning a local moves it out instead of copying it
+                match &value.val {
+                    typed_ast::ExprInner::Ident(name)
+                        if ctx.names.get_symbol(name).is_some_and(|s| s.is_local) => {}
+                    _ => check_not_copied(&value, ctx)?,
+                }
+                typed_ast::Stmt::ReturnStmt(typed_ast::ReturnStmt { value })
+            }
+            Stmt::WhileStmt(stmt) => typed_ast::Stmt::WhileStmt(stmt.to_typed(ctx)?),
+            Stmt::ForStmt(stmt) => typed_ast::Stmt::ForStmt(stmt.to_typed(ctx)?),
+            Stmt::IfStmt(stmt) => typed_ast::Stmt::IfStmt(stmt.to_typed(ctx)?),
+            Stmt::BreakStmt(jump) => typed_ast::Stmt::BreakStmt(check_jump(jump, "break", ctx)?),
+            Stmt::ContinueStmt(jump) => {
+                typed_ast::Stmt::ContinueStmt(check_jump(jump, "continue", ctx)?)
+            }
+        })
+    }
+}
+
+fn check_jump(
+    jump: ast::LoopJump,
+    keyword: &str,
+    ctx: &TypeContext,
+) -> Result<typed_ast::LoopJump, TypeCheckerError> {
+    if !ctx.in_loop(None) {
+        return Err(TypeCheckerError::OutsideLoop(keyword.to_owned(), jump.span));
+    }
+    if let Some(label) = &jump.label {
+        if !ctx.in_loop(Some(label)) {
+            return Err(TypeCheckerError::UnknownLabel(label.clone(), jump.span));
         }
     }
+
+    Ok(typed_ast::LoopJump { label: jump.label })
 }
 
 impl ToTyped for ast::BlockStmt {
     type Typed = typed_ast::BlockStmt;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        names.push_scope();
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        ctx.names.push_scope();
         let new_inners = self
-            .0
+            .stmts
             .into_iter()
-            .map(|stmt| stmt.to_typed(names))
-            .collect();
-        names.pop_scope().unwrap();
-        typed_ast::BlockStmt { inners: new_inners }
+            .map(|stmt| stmt.to_typed(ctx))
+            .collect::<Result<_, _>>();
+        ctx.names.pop_scope().unwrap();
+        Ok(typed_ast::BlockStmt {
+            inners: new_inners?,
+        })
     }
 }
 
 impl ToTyped for ast::IfStmt {
     type Typed = typed_ast::IfStmt;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        typed_ast::IfStmt {
-            condition: self.condition.to_typed(names),
-            body: self.body.to_typed(names),
-            else_stmt: self.else_stmt.map(|e| e.to_typed(names)),
-        }
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        Ok(typed_ast::IfStmt {
+            condition: coerce(self.condition.to_typed(ctx)?, &typed_ast::Type::Bool, ctx)?,
+            body: self.body.to_typed(ctx)?,
+            else_stmt: self.else_stmt.map(|e| e.to_typed(ctx)).transpose()?,
+        })
     }
 }
 
 impl ToTyped for ast::IfOrElse {
     type Typed = typed_ast::IfOrElse;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        match self {
-            ast::IfOrElse::If(if_stmt) => {
-                typed_ast::IfOrElse::If(Box::new(if_stmt.to_typed(names)))
-            }
-            ast::IfOrElse::Else(block) => typed_ast::IfOrElse::Else(block.to_typed(names)),
-        }
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        Ok(match self {
+            ast::IfOrElse::If(if_stmt) => typed_ast::IfOrElse::If(Box::new(if_stmt.to_typed(ctx)?)),
+            ast::IfOrElse::Else(block) => typed_ast::IfOrElse::Else(block.to_typed(ctx)?),
+        })
     }
 }
 
 impl ToTyped for ast::WhileStmt {
     type Typed = typed_ast::WhileStmt;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        typed_ast::WhileStmt {
-            condition: self.condition.to_typed(names),
-            body: self.body.to_typed(names),
-        }
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let condition = coerce(self.condition.to_typed(ctx)?, &typed_ast::Type::Bool, ctx)?;
+        ctx.push_loop(self.label.clone());
+        let body = self.body.to_typed(ctx);
+        ctx.pop_loop();
+        Ok(typed_ast::WhileStmt {
+            label: self.label,
+            condition,
+            body: body?,
+        })
+    }
+}
+
+impl ToTyped for ast::ForStmt {
+    type Typed = typed_ast::ForStmt;
+
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let (iter, ty) = match self.iter {
+            ast::ForIter::Range(start, end) => {
+                let span = ast::Span::new(start.span.start, end.span.end);
+                let (start, end) =
+                    unify(start.to_typed(ctx)?, end.to_typed(ctx)?).map_err(|(start, end)| {
+                        TypeCheckerError::Mismatch {
+                            expected: start,
+                            found: end,
+                            span,
+                        }
+                    })?;
+                if !start.ty.is_integer() {
+                    return Err(TypeCheckerError::NotIterable(start.ty, span));
+                }
+                let ty = start.ty.clone();
+                (typed_ast::ForIter::Range(start, end), ty)
+            }
+            ast::ForIter::Array(array) => {
+                let array = array.to_typed(ctx)?;
+                let ty = match &array.ty {
+                    typed_ast::Type::Array(inner, _) => *inner.clone(),
+                    other => return Err(TypeCheckerError::NotIterable(other.clone(), array.span)),
+                };
+                (typed_ast::ForIter::Array(array), ty)
+            }
+        };
+
+        // The loop variable gets a scope of its own around the body
+        ctx.names.push_scope();
+        ctx.names
+            .add_symbol(
+                self.name.clone(),
+                TypeSymbol::new(ty.clone(), SymbolType::Const),
+            )
+            .unwrap();
+        ctx.push_loop(self.label.clone());
+        let body = self.body.to_typed(ctx);
+        ctx.pop_loop();
+        ctx.names.pop_scope().unwrap();
+
+        Ok(typed_ast::ForStmt {
+            label: self.label,
+            binding: typed_ast::TypeBinding {
+                name: self.name,
+                ty,
+            },
+            iter,
+            body: body?,
+        })
     }
 }
 
 impl ToTyped for ast::ConstDef {
     type Typed = typed_ast::ConstDef;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        let new_binding = self.binding.to_typed(names);
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let new_binding = self.binding.to_typed(ctx)?;
+        // The value is checked before the name is in scope
+        let value = coerce(self.value.to_typed(ctx)?, &new_binding.ty, ctx)?;
+        check_not_copied(&value, ctx)?;
 
-        names
-            .add_symbol(new_binding.name.clone(), new_binding.ty.clone())
+        ctx.names
+            .add_symbol(
+                new_binding.name.clone(),
+                TypeSymbol::local(new_binding.ty.clone(), SymbolType::Const),
+            )
             .unwrap();
 
-        typed_ast::ConstDef {
+        Ok(typed_ast::ConstDef {
             binding: new_binding,
-            value: self.value.to_typed(names),
-        }
+            value,
+        })
     }
 }
 
 impl ToTyped for ast::VarDef {
     type Typed = typed_ast::VarDef;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        let new_binding = self.binding.to_typed(names);
-        names
-            .add_symbol(new_binding.name.clone(), new_binding.ty.clone())
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        let new_binding = self.binding.to_typed(ctx)?;
+        let value = coerce(self.value.to_typed(ctx)?, &new_binding.ty, ctx)?;
+        check_not_copied(&value, ctx)?;
+
+        ctx.names
+            .add_symbol(
+                new_binding.name.clone(),
+                TypeSymbol::local(new_binding.ty.clone(), SymbolType::Var),
+            )
             .unwrap();
-        typed_ast::VarDef {
+
+        Ok(typed_ast::VarDef {
             binding: new_binding,
-            value: self.value.to_typed(names),
-        }
+            value,
+        })
     }
 }
 
 impl ToTyped for ast::Expr {
     type Typed = typed_ast::Expr;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        use ast::Expr;
-        match self {
-            Expr::Class(class_expr) => {
-                let ty = names.get_symbol(&class_expr.class).unwrap().clone();
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        use ast::ExprInner;
+        use typed_ast::Type;
+
+        let span = self.span;
+        let mut val = self.val;
+        if let Some(lit) = negated_literal(&val) {
+            val = ExprInner::Literal(lit);
+        }
+        match &mut val {
+            ExprInner::FunctionCall(call) => resolve_class_instance(&mut call.name, ctx)?,
+            ExprInner::Member(object, _) => resolve_class_instance(object, ctx)?,
+            _ => (),
+        }
+
+        Ok(match val {
+            ExprInner::Class(class_expr) => {
+                let values: Vec<(String, typed_ast::Expr)> = class_expr
+                    .fields
+                    .into_iter()
+                    .map(|(name, e)| Ok((name, e.to_typed(ctx)?)))
+                    .collect::<Result<_, _>>()?;
+
+                let class = match ctx.generic(&class_expr.class) {
+                    Some(Generic::Class(generic)) => {
+                        let args = if class_expr.type_args.is_empty() {
+                            let pairs = values.iter().filter_map(|(name, e)| {
+                                let field = generic.fields.iter().find(|f| f.name == *name)?;
+                                Some((&field.ty, &e.ty))
+                            });
+                            infer_type_args(&class_expr.class, pairs, span, ctx)?
+                        } else {
+                            check_types(class_expr.type_args, ctx, span)?
+                        };
+                        instantiate(&class_expr.class, args, span, ctx)?
+                    }
+                    _ if !class_expr.type_args.is_empty() => {
+                        return Err(not_generic(
+                            &class_expr.class,
+                            class_expr.type_args.len(),
+                            span,
+                        ))
+                    }
+                    _ => class_expr.class,
+                };
+                let def = ctx
+                    .class(&class)
+                    .cloned()
+                    .ok_or_else(|| TypeCheckerError::UnknownClass(class.clone(), span))?;
+
+                let mut fields = vec![];
+                for (name, e) in values {
+                    let field_ty = match def.field(&name) {
+                        Some(field) => {
+                            check_access(&field.owner, field.is_pub, &name, e.span, ctx)?;
+                            field.ty.clone()
+                        }
+                        None => {
+                            return Err(TypeCheckerError::UnknownField {
+                                class: def.name,
+                                field: name,
+                                span: e.span,
+                            })
+                        }
+                    };
+                    if fields.iter().any(|(f, _)| *f == name) {
+                        return Err(TypeCheckerError::DuplicateField {
+                            class: def.name,
+                            field: name,
+                            span: e.span,
+                        });
+                    }
+                    let value = coerce(e, &field_ty, ctx)?;
+                    check_not_copied(&value, ctx)?;
+                    fields.push((name, Box::new(value)));
+                }
+
+                if let Some(missing) = def
+                    .fields
+                    .iter()
+                    .find(|field| !fields.iter().any(|(f, _)| *f == field.name))
+                {
+                    return Err(TypeCheckerError::MissingField {
+                        class: def.name.clone(),
+                        field: missing.name.clone(),
+                        span,
+                    });
+                }
+
                 typed_ast::Expr {
-                    ty,
-                    val: typed_ast::ExprInner::Class(typed_ast::ClassExpr {
-                        class: class_expr.class,
-                        fields: class_expr
-                            .fields
-                            .into_iter()
-                            .map(|(name, e)| (name, Box::new(e.to_typed(names))))
-                            .collect(),
-                    }),
+                    ty: Type::Class(def.name.clone()),
+                    val: typed_ast::ExprInner::Class(typed_ast::ClassExpr { class, fields }),
+                    span,
                 }
             }
-            Expr::Array(items, len) => {
-                // TODO: Make sure items all have same type
-                let new_items: Vec<typed_ast::Expr> =
-                    items.into_iter().map(|item| item.to_typed(names)).collect();
+            ExprInner::Array(items, _len) => {
+                let mut items = items.into_iter();
+                let first = items
+                    .next()
+                    .ok_or(TypeCheckerError::EmptyArray(span))?
+                    .to_typed(ctx)?;
+                let item_ty = first.ty.clone();
+
+                let mut new_items = vec![first];
+                for item in items {
+                    new_items.push(coerce(item.to_typed(ctx)?, &item_ty, ctx)?);
+                }
+                for item in new_items.iter() {
+                    check_not_copied(item, ctx)?;
+                }
+
                 typed_ast::Expr {
-                    ty: typed_ast::Type::Array(Box::new(new_items[0].ty.clone()), new_items.len()),
-                    val: typed_ast::ExprInner::Array(typed_ast::ArrayExpr {
-                        items: new_items,
-                        len,
-                    }),
+                    ty: Type::Array(Box::new(item_ty), new_items.len()),
+                    val: typed_ast::ExprInner::Array(typed_ast::ArrayExpr { items: new_items }),
+                    span,
+                }
+            }
+            ExprInner::Binary(lhs, op, rhs) => {
+                let new_lhs = lhs.to_typed(ctx)?;
+                let new_rhs = rhs.to_typed(ctx)?;
+                let new_op = op.to_typed(ctx)?;
+                match new_lhs.ty {
+                    Type::Class(_) if new_op != typed_ast::BinOp::Equals => {
+                        check_overloaded(new_lhs, new_op, new_rhs, span, ctx)?
+                    }
+                    _ => check_binary(new_lhs, new_op, new_rhs, span, ctx)?,
                 }
             }
-            Expr::Binary(lhs, op, rhs) => {
-                let new_lhs = lhs.to_typed(names);
-                let new_rhs = rhs.to_typed(names);
-                let new_op = op.to_typed(names);
+            ExprInner::CompoundAssign(lhs, op, rhs) => {
+                let lhs = lhs.to_typed(ctx)?;
+                let rhs = rhs.to_typed(ctx)?;
+                let op = op.to_typed(ctx)?;
+                check_assignable(&lhs, ctx)?;
+                let method = match &lhs.ty {
+                    Type::Class(class) => ctx
+                        .class(class)
+                        .and_then(|def| def.method(&typed_ast::operator_method(op)))
+                        .cloned(),
+                    _ => None,
+                };
+                let rhs = if let Some(method) = method {
+                    // Objects store what their operator method returns
+                    let call = check_method_call(lhs.clone(), &method, vec![rhs], span, ctx)?;
+                    if call.ty != lhs.ty {
+                        return Err(TypeCheckerError::Mismatch {
+                            expected: lhs.ty,
+                            found: call.ty,
+                            span,
+                        });
+                    }
+                    call
+                } else if lhs.ty.is_integer() || lhs.ty.is_float() {
+                    coerce(rhs, &lhs.ty, ctx)?
+                } else {
+                    return Err(TypeCheckerError::InvalidOperands {
+                        op,
+                        lhs: lhs.ty,
+                        rhs: rhs.ty,
+                        span,
+                    });
+                };
+
                 typed_ast::Expr {
-                    ty: new_lhs.ty.clone(),
-                    val: typed_ast::ExprInner::Binary(typed_ast::BinaryExpr {
-                        lhs: Box::new(new_lhs),
-                        op: new_op,
-                        rhs: Box::new(new_rhs),
+                    ty: Type::NoneType,
+                    val: typed_ast::ExprInner::CompoundAssign(typed_ast::BinaryExpr {
+                        lhs: Box::new(lhs),
+                        op,
+                        rhs: Box::new(rhs),
                     }),
+                    span,
                 }
             }
-            Expr::Cast(original, to_type) => {
-                let new_type = to_type.to_typed(names);
+            ExprInner::Cast(original, to_type) => {
+                let new_type = check_type(to_type, ctx, span)?;
+                let mut original = original.to_typed(ctx)?;
+                // So that `0 as *int` can still make a null pointer
+                if matches!(new_type, Type::Ref(_) | Type::CStr) && is_untyped_literal(&original) {
+                    original = coerce_literal(original, &Type::Int64)?;
+                }
+                if !can_cast(&original.ty, &new_type) {
+                    return Err(TypeCheckerError::InvalidCast {
+                        from: original.ty,
+                        to: new_type,
+                        span,
+                    });
+                }
+
                 typed_ast::Expr {
                     ty: new_type.clone(),
                     val: typed_ast::ExprInner::Cast(typed_ast::CastExpr {
-                        original: Box::new(original.to_typed(names)),
+                        original: Box::new(original),
                         to_type: new_type,
                     }),
+                    span,
                 }
             }
-            Expr::FunctionCall(call) => {
-                // TODO: Handle error better
-                let new_name = call.name.to_typed(names);
-                let return_type = new_name.ty.clone();
+            ExprInner::FunctionCall(call) if is_builtin_len(&call, ctx) => {
+                if call.args.len() != 1 {
+                    return Err(TypeCheckerError::ArgCount {
+                        expected: 1,
+                        found: call.args.len(),
+                        span,
+                    });
+                }
+                let arg = call.args.into_iter().next().unwrap().to_typed(ctx)?;
+                if !matches!(arg.ty, Type::Str | Type::Array(..)) {
+                    return Err(TypeCheckerError::InvalidLen(arg.ty, arg.span));
+                }
+
                 typed_ast::Expr {
-                    ty: return_type,
-                    val: typed_ast::ExprInner::FunctionCall(typed_ast::FunctionCall {
-                        name: Box::new(new_name),
-                        args: call.args.into_iter().map(|a| a.to_typed(names)).collect(),
-                    }),
+                    ty: Type::Int,
+                    val: typed_ast::ExprInner::Len(Box::new(arg)),
+                    span,
+                }
+            }
+            // A generic used without type arguments gets them from the arguments
+            ExprInner::FunctionCall(call) if generic_name(&call.name, ctx).is_some() => {
+                let name = generic_name(&call.name, ctx).unwrap();
+                let args: Vec<typed_ast::Expr> = call
+                    .args
+                    .into_iter()
+                    .map(|arg| arg.to_typed(ctx))
+                    .collect::<Result<_, _>>()?;
+
+                let generic = ctx.generic(&name).unwrap().clone();
+                let params: Vec<&ast::Type> = match &generic {
+                    Generic::Class(def) => def
+                        .methods
+                        .iter()
+                        .find(|m| m.func.name == typed_ast::INIT)
+                        .ok_or_else(|| TypeCheckerError::NoInit(name.clone(), span))?
+                        .func
+                        .params
+                        .iter()
+                        .map(|p| &p.ty)
+                        .collect(),
+                    Generic::Func(def) => def.params.iter().map(|p| &p.ty).collect(),
+                };
+                if params.len() != args.len() {
+                    return Err(TypeCheckerError::ArgCount {
+                        expected: params.len(),
+                        found: args.len(),
+                        span,
+                    });
+                }
+                let pairs = params.into_iter().zip(args.iter().map(|arg| &arg.ty));
+                let type_args = infer_type_args(&name, pairs, span, ctx)?;
+                let instance = instantiate(&name, type_args, span, ctx)?;
+
+                match generic {
+                    Generic::Class(_) => check_construct(instance, args, span, ctx)?,
+                    Generic::Func(_) => {
+                        let callee = typed_ast::Expr {
+                            ty: ctx.names.get_symbol(&instance).unwrap().ty.clone(),
+                            val: typed_ast::ExprInner::Ident(instance),
+                            span: call.name.span,
+                        };
+                        check_call(callee, None, typed_ast::Dispatch::Static, args, span, ctx)?
+                    }
                 }
             }
-            Expr::Ident(ident) => {
-                // TODO: Handle error better
-                let ty = names
-                    .get_symbol(&ident)
-                    .expect(format!("Could not find ident: {}", ident).as_str())
-                    .clone();
+            ExprInner::FunctionCall(call) if variadic_name(&call.name, ctx).is_some() => {
+                let name = variadic_name(&call.name, ctx).unwrap();
+                let mut args: Vec<typed_ast::Expr> = call
+                    .args
+                    .into_iter()
+                    .map(|arg| arg.to_typed(ctx))
+                    .collect::<Result<_, _>>()?;
+                let callee = typed_ast::Expr {
+                    ty: ctx.names.get_symbol(&name).unwrap().ty.clone(),
+                    val: typed_ast::ExprInner::Ident(name),
+                    span: call.name.span,
+                };
+
+                let fixed = match &callee.ty {
+                    Type::Function(params, _) => params.len(),
+                    _ => unreachable!("externs are functions"),
+                };
+                if args.len() < fixed {
+                    return Err(TypeCheckerError::ArgCount {
+                        expected: fixed,
+                        found: args.len(),
+                        span,
+                    });
+                }
+                let extra = args
+                    .split_off(fixed)
+                    .into_iter()
+                    .map(promote_vararg)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut expr =
+                    check_call(callee, None, typed_ast::Dispatch::Static, args, span, ctx)?;
+                if let typed_ast::ExprInner::FunctionCall(call) = &mut expr.val {
+                    call.args.extend(extra);
+                }
+                expr
+            }
+            ExprInner::FunctionCall(call) if class_name_expr(&call.name, ctx).is_some() => {
+                let class = class_name_expr(&call.name, ctx).unwrap();
+                let args: Vec<typed_ast::Expr> = call
+                    .args
+                    .into_iter()
+                    .map(|arg| arg.to_typed(ctx))
+                    .collect::<Result<_, _>>()?;
+                check_construct(class, args, span, ctx)?
+            }
+            ExprInner::FunctionCall(call) => {
+                let (new_name, receiver, dispatch) = check_callee(*call.name, ctx)?;
+                let args: Vec<typed_ast::Expr> = call
+                    .args
+                    .into_iter()
+                    .map(|arg| arg.to_typed(ctx))
+                    .collect::<Result<_, _>>()?;
+                check_call(new_name, receiver, dispatch, args, span, ctx)?
+            }
+            ExprInner::Ident(ident) => {
+                let ty = match ctx.names.get_symbol(&ident) {
+                    Some(symbol) => symbol.ty.clone(),
+                    None => return Err(unknown_name(&ident, span, ctx)),
+                };
+                // Its type can't say that it takes extra arguments
+                if is_variadic_extern(&ident, ctx) {
+                    return Err(TypeCheckerError::VariadicValue(ident, span));
+                }
                 typed_ast::Expr {
-                    ty: ty.clone(),
+                    ty,
                     val: typed_ast::ExprInner::Ident(ident),
+                    span,
+                }
+            }
+            ExprInner::Instance(name, args) => {
+                if !matches!(ctx.generic(&name), Some(Generic::Func(_))) {
+                    return Err(match ctx.names.get_symbol(&name) {
+                        Some(_) => not_generic(&name, args.len(), span),
+                        None => TypeCheckerError::UnknownName(name, span),
+                    });
+                }
+                let args = check_types(args, ctx, span)?;
+                let instance = instantiate(&name, args, span, ctx)?;
+                typed_ast::Expr {
+                    ty: ctx.names.get_symbol(&instance).unwrap().ty.clone(),
+                    val: typed_ast::ExprInner::Ident(instance),
+                    span,
                 }
             }
-            Expr::IndexExpr(name, index) => {
-                // TODO: Handle error better
-                let new_name = name.to_typed(names);
-                use typed_ast::Type;
+            ExprInner::IndexExpr(name, index) => {
+                let new_name = name.to_typed(ctx)?;
+                if let Type::Class(class) = &new_name.ty {
+                    if let Some(method) = ctx.class(class).unwrap().method(typed_ast::INDEX_METHOD)
+                    {
+                        let method = method.clone();
+                        let new_index = index.to_typed(ctx)?;
+                        let call =
+                            check_method_call(new_name, &method, vec![new_index], span, ctx)?;
+                        // Returning a pointer lets `object[index]` be assigned to
+                        return Ok(match call.ty.clone() {
+                            Type::Ref(inner) if !call.ty.is_interface_ref() => typed_ast::Expr {
+                                ty: *inner,
+                                val: typed_ast::ExprInner::Unary(typed_ast::UnaryExpr {
+                                    data: Box::new(call),
+                                    op: typed_ast::UnaryOp::Deref,
+                                }),
+                                span,
+                            },
+                            _ => call,
+                        });
+                    }
+                }
                 let inner_type = match &new_name.ty {
                     Type::Array(ty, _len) => *ty.clone(),
-                    Type::Ref(ty) => *ty.clone(),
-                    _ => panic!("Index into non-array type"),
+                    Type::Ref(ty) if !new_name.ty.is_interface_ref() => *ty.clone(),
+                    Type::Str | Type::CStr => Type::Char,
+                    other => {
+                        return Err(TypeCheckerError::NotIndexable(other.clone(), new_name.span))
+                    }
                 };
+                let new_index = index.to_typed(ctx)?;
+                if !new_index.ty.is_integer() {
+                    return Err(TypeCheckerError::Mismatch {
+                        expected: Type::Int,
+                        found: new_index.ty,
+                        span: new_index.span,
+                    });
+                }
                 typed_ast::Expr {
                     ty: inner_type,
                     val: typed_ast::ExprInner::IndexExpr(typed_ast::IndexExpr {
                         name: Box::new(new_name),
-                        index: Box::new(index.to_typed(names)),
+                        index: Box::new(new_index),
                     }),
+                    span,
                 }
             }
-            Expr::Literal(lit) => {
-                let new_lit = lit.to_typed(names);
+            ExprInner::Member(object, field) => match class_name_expr(&object, ctx) {
+                // `Class.method` is the method's function itself
+                Some(class) => {
+                    let method = ctx.class(&class).unwrap().method(&field).ok_or_else(|| {
+                        TypeCheckerError::UnknownMethod {
+                            class: class.clone(),
+                            method: field.clone(),
+                            span,
+                        }
+                    })?;
+                    check_access(&method.owner, method.is_pub, &field, span, ctx)?;
+                    typed_ast::Expr {
+                        ty: method.ty.clone(),
+                        val: typed_ast::ExprInner::Ident(typed_ast::method_symbol(
+                            &method.owner,
+                            &field,
+                        )),
+                        span,
+                    }
+                }
+                None => check_member(object.to_typed(ctx)?, field, span, ctx)?,
+            },
+            ExprInner::Literal(lit) => {
+                let new_lit = lit.to_typed(ctx)?;
                 typed_ast::Expr {
                     ty: new_lit.ty.clone(),
                     val: typed_ast::ExprInner::Literal(new_lit),
+                    span,
                 }
             }
-            Expr::Unary(op, data) => {
-                use ast::UnaryOp;
-                let new_data = data.to_typed(names);
-                let expr_ty = match op {
-                    UnaryOp::Reference => typed_ast::Type::Ref(Box::new(new_data.ty.clone())),
-                    _ => new_data.ty.clone(),
+            ExprInner::Unary(op, data) => {
+                let new_data = data.to_typed(ctx)?;
+                let new_op = op.to_typed(ctx)?;
+                let invalid = || TypeCheckerError::InvalidOperand {
+                    op: new_op,
+                    ty: new_data.ty.clone(),
+                    span,
+                };
+                let expr_ty = match new_op {
+                    typed_ast::UnaryOp::Reference => {
+                        if !is_addressable(&new_data) {
+                            return Err(TypeCheckerError::NotAddressable(new_data.span));
+                        }
+                        Type::Ref(Box::new(new_data.ty.clone()))
+                    }
+                    typed_ast::UnaryOp::Deref => match &new_data.ty {
+                        Type::Ref(inner) if !new_data.ty.is_interface_ref() => *inner.clone(),
+                        _ => return Err(invalid()),
+                    },
+                    typed_ast::UnaryOp::Minus
+                        if new_data.ty.is_integer() || new_data.ty.is_float() =>
+                    {
+                        new_data.ty.clone()
+                    }
+                    typed_ast::UnaryOp::Not if new_data.ty == Type::Bool => Type::Bool,
+                    _ => return Err(invalid()),
                 };
                 typed_ast::Expr {
                     ty: expr_ty,
                     val: typed_ast::ExprInner::Unary(typed_ast::UnaryExpr {
                         data: Box::new(new_data),
-                        op: op.to_typed(names),
+                        op: new_op,
                     }),
+                    span,
                 }
             }
+        })
+    }
+}
+
+/// `-` and an unsuffixed literal as one negative literal, so that the minus
+/// does not push the literal out of range first, as in `-2147483648`.
+fn negated_literal(val: &ast::ExprInner) -> Option<ast::Literal> {
+    use ast::{ExprInner, Literal};
+
+    match val {
+        ExprInner::Unary(ast::UnaryOp::Minus, data) => match data.val {
+            ExprInner::Literal(Literal::Int(value)) => Some(Literal::Int(-value)),
+            ExprInner::Literal(Literal::Double(value)) => Some(Literal::Double(-value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Type `object.field`.
+fn check_member(
+    object: typed_ast::Expr,
+    field: String,
+    span: ast::Span,
+    ctx: &TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    let class = match member_class(&object.ty) {
+        Some(class) => class.to_owned(),
+        None => return Err(TypeCheckerError::NoFields(object.ty, object.span)),
+    };
+
+    let def = ctx.class(&class).unwrap();
+    let (index, ty) = match def.field(&field) {
+        Some(f) => {
+            check_access(&f.owner, f.is_pub, &field, span, ctx)?;
+            (def.field_index(&field).unwrap(), f.ty.clone())
+        }
+        None if def.method(&field).is_some() => {
+            return Err(TypeCheckerError::MethodValue {
+                class,
+                method: field,
+                span,
+            })
+        }
+        None => return Err(TypeCheckerError::UnknownField { class, field, span }),
+    };
+
+    Ok(typed_ast::Expr {
+        ty,
+        val: typed_ast::ExprInner::Member(typed_ast::MemberExpr {
+            object: Box::new(object),
+            index,
+        }),
+        span,
+    })
+}
+
+/// Type `Class(args)`, which makes an object and passes it to `init`.
+fn check_construct(
+    class: String,
+    args: Vec<typed_ast::Expr>,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    let init = ctx
+        .class(&class)
+        .unwrap()
+        .method(typed_ast::INIT)
+        .ok_or_else(|| TypeCheckerError::NoInit(class.clone(), span))?;
+    check_access(&init.owner, init.is_pub, &init.name, span, ctx)?;
+    let param_types = match &init.ty {
+        typed_ast::Type::Function(params, _) => params[1..].to_vec(),
+        _ => unreachable!("methods are functions"),
+    };
+    if param_types.len() != args.len() {
+        return Err(TypeCheckerError::ArgCount {
+            expected: param_types.len(),
+            found: args.len(),
+            span,
+        });
+    }
+
+    let args = args
+        .into_iter()
+        .zip(param_types.iter())
+        .map(|(arg, ty)| coerce(arg, ty, ctx))
+        .collect::<Result<_, _>>()?;
+
+    Ok(typed_ast::Expr {
+        ty: typed_ast::Type::Class(class.clone()),
+        val: typed_ast::ExprInner::Construct(typed_ast::ConstructExpr { class, args }),
+        span,
+    })
+}
+
+/// Type a call of `callee` with `args`. The receiver of a method call is
+/// passed as `self`.
+fn check_call(
+    callee: typed_ast::Expr,
+    receiver: Option<typed_ast::Expr>,
+    dispatch: typed_ast::Dispatch,
+    args: Vec<typed_ast::Expr>,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    let (param_types, return_type) = match &callee.ty {
+        typed_ast::Type::Function(params, return_type) => (params.clone(), *return_type.clone()),
+        other => return Err(TypeCheckerError::NotCallable(other.clone(), callee.span)),
+    };
+    let receiver_count = usize::from(receiver.is_some());
+    if param_types.len() != args.len() + receiver_count {
+        return Err(TypeCheckerError::ArgCount {
+            expected: param_types.len() - receiver_count,
+            found: args.len(),
+            span,
+        });
+    }
+
+    let args = receiver
+        .into_iter()
+        .chain(args)
+        .zip(param_types.iter())
+        .map(|(arg, ty)| coerce(arg, ty, ctx))
+        .collect::<Result<_, _>>()?;
+
+    Ok(typed_ast::Expr {
+        ty: return_type,
+        val: typed_ast::ExprInner::FunctionCall(typed_ast::FunctionCall {
+            name: Box::new(callee),
+            args,
+            dispatch,
+        }),
+        span,
+    })
+}
+
+/// Type the callee of a call. For `obj.method(...)` this is the method's
+/// function, and `obj` is returned as the receiver to pass as `self`, along
+/// with how the function is found.
+fn check_callee(
+    callee: ast::Expr,
+    ctx: &mut TypeContext,
+) -> Result<
+    (
+        typed_ast::Expr,
+        Option<typed_ast::Expr>,
+        typed_ast::Dispatch,
+    ),
+    TypeCheckerError,
+> {
+    use typed_ast::Type;
+
+    let span = callee.span;
+    let (object, name) = match callee.val {
+        ast::ExprInner::Member(object, name) if class_name_expr(&object, ctx).is_none() => {
+            (object, name)
+        }
+        val => {
+            return Ok((
+                ast::Expr::new(val, span).to_typed(ctx)?,
+                None,
+                typed_ast::Dispatch::Static,
+            ))
+        }
+    };
+
+    let object = object.to_typed(ctx)?;
+    if let Type::Ref(inner) = &object.ty {
+        if let Type::Interface(interface) = &**inner {
+            let method = ctx
+                .interface(interface)
+                .unwrap()
+                .method(&name)
+                .ok_or_else(|| TypeCheckerError::UnknownMethod {
+                    class: interface.clone(),
+                    method: name.clone(),
+                    span,
+                })?;
+            let callee = typed_ast::Expr {
+                ty: method.ty.clone(),
+                val: typed_ast::ExprInner::Ident(typed_ast::method_symbol(interface, &name)),
+                span,
+            };
+            let dispatch = typed_ast::Dispatch::Interface(method.slot.unwrap());
+            return Ok((callee, Some(object), dispatch));
+        }
+    }
+
+    let class = match member_class(&object.ty) {
+        Some(class) => class.to_owned(),
+        None => {
+            return Ok((
+                check_member(object, name, span, ctx)?,
+                None,
+                typed_ast::Dispatch::Static,
+            ))
         }
+    };
+    let method = match ctx.class(&class).unwrap().method(&name) {
+        Some(method) => method.clone(),
+        // Calling a function stored in a field
+        None => {
+            return Ok((
+                check_member(object, name, span, ctx)?,
+                None,
+                typed_ast::Dispatch::Static,
+            ))
+        }
+    };
+    check_access(&method.owner, method.is_pub, &name, span, ctx)?;
+    if method.is_static {
+        return Err(TypeCheckerError::StaticOnObject {
+            class,
+            method: name,
+            span,
+        });
+    }
+
+    let (callee, dispatch) = method_callee(&method, span);
+    Ok((callee, Some(receiver(object)), dispatch))
+}
+
+/// The function to call for a non-static `method`, and how to find it.
+fn method_callee(
+    method: &typed_ast::Method,
+    span: ast::Span,
+) -> (typed_ast::Expr, typed_ast::Dispatch) {
+    let callee = typed_ast::Expr {
+        ty: method.ty.clone(),
+        val: typed_ast::ExprInner::Ident(typed_ast::method_symbol(&method.owner, &method.name)),
+        span,
+    };
+    let dispatch = match method.slot {
+        Some(slot) => typed_ast::Dispatch::Virtual(slot),
+        None => typed_ast::Dispatch::Static,
+    };
+    (callee, dispatch)
+}
+
+/// Methods take a pointer, so objects are passed by reference.
+fn receiver(object: typed_ast::Expr) -> typed_ast::Expr {
+    match object.ty {
+        typed_ast::Type::Ref(_) => object,
+        _ => typed_ast::Expr {
+            ty: typed_ast::Type::Ref(Box::new(object.ty.clone())),
+            span: object.span,
+            val: typed_ast::ExprInner::Unary(typed_ast::UnaryExpr {
+                data: Box::new(object),
+                op: typed_ast::UnaryOp::Reference,
+            }),
+        },
+    }
+}
+
+/// Type `object.method(args)` for a method the checker picked itself, such
+/// as the one for an operator.
+fn check_method_call(
+    object: typed_ast::Expr,
+    method: &typed_ast::Method,
+    args: Vec<typed_ast::Expr>,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    check_access(&method.owner, method.is_pub, &method.name, span, ctx)?;
+    let (callee, dispatch) = method_callee(method, span);
+    check_call(callee, Some(receiver(object)), dispatch, args, span, ctx)
+}
+
+/// Type a binary expression on an object, which calls the method its class
+/// defines for the operator. Without one, `a != b` is `not (a == b)` and
+/// `a >= b` is `not (a < b)`.
+fn check_overloaded(
+    lhs: typed_ast::Expr,
+    op: typed_ast::BinOp,
+    rhs: typed_ast::Expr,
+    span: ast::Span,
+    ctx: &mut TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    use typed_ast::{BinOp, Type};
+
+    let def = match &lhs.ty {
+        Type::Class(class) => ctx.class(class).unwrap(),
+        _ => unreachable!("only objects have operator methods"),
+    };
+    let fallback = match op {
+        BinOp::Ne => Some(BinOp::Eq),
+        BinOp::Gte => Some(BinOp::Lt),
+        _ => None,
+    };
+    let (method, negate) = match def.method(&typed_ast::operator_method(op)) {
+        Some(method) => (method.clone(), false),
+        None => match fallback.and_then(|op| def.method(&typed_ast::operator_method(op))) {
+            Some(method) => (method.clone(), true),
+            None => return check_binary(lhs, op, rhs, span, ctx),
+        },
+    };
+
+    let mut call = check_method_call(lhs, &method, vec![rhs], span, ctx)?;
+    let is_comparison = matches!(
+        op,
+        BinOp::Eq | BinOp::Ne | BinOp::Gt | BinOp::Gte | BinOp::Lt | BinOp::Lte
+    );
+    // Comparisons may return an integer, which is true when non-zero
+    if is_comparison && call.ty != Type::Bool {
+        call = typed_ast::Expr {
+            ty: Type::Bool,
+            val: typed_ast::ExprInner::Cast(typed_ast::CastExpr {
+                original: Box::new(call),
+                to_type: Type::Bool,
+            }),
+            span,
+        };
+    }
+    if negate {
+        call = typed_ast::Expr {
+            ty: Type::Bool,
+            val: typed_ast::ExprInner::Unary(typed_ast::UnaryExpr {
+                data: Box::new(call),
+                op: typed_ast::UnaryOp::Not,
+            }),
+            span,
+        };
+    }
+    Ok(call)
+}
+
+/// Check that a member declared by `owner` can be used here.
+fn check_access(
+    owner: &str,
+    is_pub: bool,
+    member: &str,
+    span: ast::Span,
+    ctx: &TypeContext,
+) -> Result<(), TypeCheckerError> {
+    if ctx.can_access(owner, is_pub) {
+        Ok(())
+    } else {
+        Err(TypeCheckerError::PrivateMember {
+            class: owner.to_owned(),
+            member: member.to_owned(),
+            span,
+        })
+    }
+}
+
+/// The class whose members `ty` has, looking through one pointer.
+fn member_class(ty: &typed_ast::Type) -> Option<&str> {
+    match ty {
+        typed_ast::Type::Class(name) => Some(name),
+        typed_ast::Type::Ref(inner) => match &**inner {
+            typed_ast::Type::Class(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `expr` names a generic class or function rather than a value, its name.
+fn generic_name(expr: &ast::Expr, ctx: &TypeContext) -> Option<String> {
+    match &expr.val {
+        ast::ExprInner::Ident(name)
+            if ctx.names.get_symbol(name).is_none() && ctx.generic(name).is_some() =>
+        {
+            Some(name.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Replace `Class::<...>`, alone or as the object of a member access, with
+/// the name of the instance, which is then used like any other class.
+fn resolve_class_instance(
+    expr: &mut ast::Expr,
+    ctx: &mut TypeContext,
+) -> Result<(), TypeCheckerError> {
+    match &mut expr.val {
+        ast::ExprInner::Member(object, _) => resolve_class_instance(object, ctx),
+        ast::ExprInner::Instance(name, args)
+            if matches!(ctx.generic(name), Some(Generic::Class(_))) =>
+        {
+            let args = check_types(args.clone(), ctx, expr.span)?;
+            let instance = instantiate(name, args, expr.span, ctx)?;
+            expr.val = ast::ExprInner::Ident(instance);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The error for a name that is not a value in scope.
+fn unknown_name(name: &str, span: ast::Span, ctx: &TypeContext) -> TypeCheckerError {
+    match ctx.generic(name) {
+        Some(Generic::Class(def)) => TypeCheckerError::TypeArgCount {
+            name: name.to_owned(),
+            expected: def.type_params.len(),
+            found: 0,
+            span,
+        },
+        Some(Generic::Func(def)) => TypeCheckerError::CannotInfer {
+            name: name.to_owned(),
+            param: def.type_params[0].clone(),
+            span,
+        },
+        None => TypeCheckerError::UnknownName(name.to_owned(), span),
+    }
+}
+
+/// The error for type arguments given to `name`, which takes none.
+fn not_generic(name: &str, found: usize, span: ast::Span) -> TypeCheckerError {
+    TypeCheckerError::TypeArgCount {
+        name: name.to_owned(),
+        expected: 0,
+        found,
+        span,
+    }
+}
+
+/// Whether `name` refers to an extern declared with `...`.
+fn is_variadic_extern(name: &str, ctx: &TypeContext) -> bool {
+    ctx.is_variadic(name)
+        && matches!(ctx.names.get_symbol(name), Some(symbol) if symbol.kind == SymbolType::Func)
+}
+
+/// If `expr` names an extern declared with `...`, its name.
+fn variadic_name(expr: &ast::Expr, ctx: &TypeContext) -> Option<String> {
+    match &expr.val {
+        ast::ExprInner::Ident(name) if is_variadic_extern(name, ctx) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Apply C's default argument promotions to an argument passed in place of
+/// `...`, which C reads as at least an `int` or a `double`.
+fn promote_vararg(arg: typed_ast::Expr) -> Result<typed_ast::Expr, TypeCheckerError> {
+    use typed_ast::Type;
+
+    let promoted = match &arg.ty {
+        Type::Float => Type::Double,
+        Type::Bool | Type::Char => Type::Int,
+        ty if ty.int_bits().is_some_and(|bits| bits < 32) => Type::Int,
+        ty if ty.is_integer() || ty.is_float() => return Ok(arg),
+        Type::CStr => return Ok(arg),
+        Type::Ref(_) if !arg.ty.is_interface_ref() => return Ok(arg),
+        other => return Err(TypeCheckerError::InvalidVarArg(other.clone(), arg.span)),
+    };
+
+    Ok(typed_ast::Expr {
+        ty: promoted.clone(),
+        span: arg.span,
+        val: typed_ast::ExprInner::Cast(typed_ast::CastExpr {
+            original: Box::new(arg),
+            to_type: promoted,
+        }),
+    })
+}
+
+/// If `expr` names a class rather than a value, the class name.
+fn class_name_expr(expr: &ast::Expr, ctx: &TypeContext) -> Option<String> {
+    match &expr.val {
+        ast::ExprInner::Ident(name)
+            if ctx.names.get_symbol(name).is_none() && ctx.class(name).is_some() =>
+        {
+            Some(name.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Type a binary expression, making sure the operands fit the operator.
+fn check_binary(
+    lhs: typed_ast::Expr,
+    op: typed_ast::BinOp,
+    rhs: typed_ast::Expr,
+    span: ast::Span,
+    ctx: &TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    use typed_ast::{BinOp, Type};
+
+    let (lhs, rhs) = if op == BinOp::Equals {
+        check_assignable(&lhs, ctx)?;
+        let rhs = coerce(rhs, &lhs.ty, ctx)?;
+        check_not_copied(&rhs, ctx)?;
+        (lhs, rhs)
+    } else if matches!(op, BinOp::ShiftLeft | BinOp::ShiftRight) {
+        // The shift amount can be any integer type
+        let rhs = coerce_literal(rhs, &lhs.ty)?;
+        (lhs, rhs)
+    } else {
+        unify(lhs, rhs).map_err(|(lhs, rhs)| TypeCheckerError::InvalidOperands {
+            op,
+            lhs,
+            rhs,
+            span,
+        })?
+    };
+
+    let operand_ty = &lhs.ty;
+    let ty = match op {
+        BinOp::Plus | BinOp::Minus | BinOp::Times | BinOp::Divide | BinOp::Remainder
+            if operand_ty.is_integer() || operand_ty.is_float() =>
+        {
+            operand_ty.clone()
+        }
+        BinOp::ShiftLeft | BinOp::ShiftRight if operand_ty.is_integer() && rhs.ty.is_integer() => {
+            operand_ty.clone()
+        }
+        BinOp::LogicalAnd | BinOp::LogicalOr if *operand_ty == Type::Bool => Type::Bool,
+        BinOp::Eq | BinOp::Ne
+            if operand_ty.is_integer()
+                || operand_ty.is_float()
+                || (matches!(
+                    operand_ty,
+                    Type::Bool | Type::Char | Type::Ref(_) | Type::CStr
+                ) && !operand_ty.is_interface_ref()) =>
+        {
+            Type::Bool
+        }
+        BinOp::Gt | BinOp::Gte | BinOp::Lt | BinOp::Lte
+            if operand_ty.is_integer() || operand_ty.is_float() || *operand_ty == Type::Char =>
+        {
+            Type::Bool
+        }
+        BinOp::Equals => Type::NoneType,
+        _ => {
+            return Err(TypeCheckerError::InvalidOperands {
+                op,
+                lhs: lhs.ty,
+                rhs: rhs.ty,
+                span,
+            })
+        }
+    };
+
+    let is_field_init = op == BinOp::Equals && is_field_init(&lhs, ctx);
+    let binary_expr = typed_ast::BinaryExpr {
+        lhs: Box::new(lhs),
+        op,
+        rhs: Box::new(rhs),
+    };
+    Ok(typed_ast::Expr {
+        ty,
+        val: if is_field_init {
+            typed_ast::ExprInner::FieldInit(binary_expr)
+        } else {
+            typed_ast::ExprInner::Binary(binary_expr)
+        },
+        span,
+    })
+}
+
+/// Whether assigning to `lhs` sets a field of the object `init` was called on.
+/// The object starts zeroed, so there is no old value to destroy.
+fn is_field_init(lhs: &typed_ast::Expr, ctx: &TypeContext) -> bool {
+    use typed_ast::ExprInner;
+
+    ctx.is_initializing()
+        && ctx.needs_drop(&lhs.ty)
+        && matches!(&lhs.val, ExprInner::Member(member)
+            if matches!(&member.object.val, ExprInner::Ident(name) if name == "self"))
+}
+
+/// Make sure storing `value` does not copy an object that has to be destroyed,
+/// since both copies would be. Only new objects can be stored; a local can
+/// also be moved out by returning it.
+fn check_not_copied(value: &typed_ast::Expr, ctx: &TypeContext) -> Result<(), TypeCheckerError> {
+    use typed_ast::ExprInner;
+
+    match &value.val {
+        _ if !ctx.needs_drop(&value.ty) => Ok(()),
+        ExprInner::Class(_)
+        | ExprInner::Array(_)
+        | ExprInner::Construct(_)
+        | ExprInner::FunctionCall(_) => Ok(()),
+        ExprInner::Cast(cast) => check_not_copied(&cast.original, ctx),
+        _ => Err(TypeCheckerError::CopiedObject(value.ty.clone(), value.span)),
+    }
+}
+
+/// Make sure `expr` can appear on the left of an assignment.
+fn check_assignable(expr: &typed_ast::Expr, ctx: &TypeContext) -> Result<(), TypeCheckerError> {
+    use typed_ast::ExprInner;
+
+    match &expr.val {
+        ExprInner::Ident(name) => match ctx.names.get_symbol(name).map(|s| s.kind) {
+            Some(SymbolType::Var) => Ok(()),
+            Some(SymbolType::Const) => {
+                Err(TypeCheckerError::AssignToConst(name.clone(), expr.span))
+            }
+            _ => Err(TypeCheckerError::NotAssignable(expr.span)),
+        },
+        // Writing through a pointer is always allowed, writing into an array
+        // is only allowed if the array itself is assignable.
+        ExprInner::IndexExpr(index) => match index.name.ty {
+            typed_ast::Type::Array(..) => check_assignable(&index.name, ctx),
+            // String data may live in read-only memory
+            typed_ast::Type::Str => Err(TypeCheckerError::NotAssignable(expr.span)),
+            _ => Ok(()),
+        },
+        ExprInner::Unary(unary) if unary.op == typed_ast::UnaryOp::Deref => Ok(()),
+        // Same as indexing: fields behind a pointer are always writable
+        ExprInner::Member(member) => match member.object.ty {
+            typed_ast::Type::Ref(_) => Ok(()),
+            _ => check_assignable(&member.object, ctx),
+        },
+        _ => Err(TypeCheckerError::NotAssignable(expr.span)),
+    }
+}
+
+/// Whether codegen can produce a pointer to `expr`.
+fn is_addressable(expr: &typed_ast::Expr) -> bool {
+    use typed_ast::ExprInner;
+
+    match &expr.val {
+        ExprInner::Ident(_) => true,
+        ExprInner::IndexExpr(index) => match index.name.ty {
+            typed_ast::Type::Str => false,
+            typed_ast::Type::Array(..) => is_addressable(&index.name),
+            _ => true,
+        },
+        ExprInner::Member(member) => match member.object.ty {
+            typed_ast::Type::Ref(_) => true,
+            _ => is_addressable(&member.object),
+        },
+        ExprInner::Unary(unary) => unary.op == typed_ast::UnaryOp::Deref,
+        _ => false,
+    }
+}
+
+/// Check that `expr` has type `expected`, letting unsuffixed integer literals
+/// take on any integer type they fit in.
+fn coerce(
+    expr: typed_ast::Expr,
+    expected: &typed_ast::Type,
+    ctx: &TypeContext,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    let span = expr.span;
+    let mut expr = coerce_literal(expr, expected)?;
+
+    if expr.ty == *expected {
+        Ok(expr)
+    } else if is_byte_pointer(&expr.ty) && is_byte_pointer(expected) {
+        // cstr is just a C char pointer, so these convert freely
+        expr.ty = expected.clone();
+        Ok(expr)
+    } else if is_upcast(&expr.ty, expected, ctx) || implements(&expr.ty, expected, ctx) {
+        // Derived objects start with their base, so the pointer is reused.
+        // Interface pointers are built by pairing it with a table.
+        Ok(typed_ast::Expr {
+            ty: expected.clone(),
+            val: typed_ast::ExprInner::Cast(typed_ast::CastExpr {
+                original: Box::new(expr),
+                to_type: expected.clone(),
+            }),
+            span,
+        })
+    } else if expr.ty == typed_ast::Type::Str && *expected == typed_ast::Type::CStr {
+        // String data is always NUL-terminated, so this just drops the length
+        Ok(typed_ast::Expr {
+            ty: typed_ast::Type::CStr,
+            val: typed_ast::ExprInner::Cast(typed_ast::CastExpr {
+                original: Box::new(expr),
+                to_type: typed_ast::Type::CStr,
+            }),
+            span,
+        })
+    } else {
+        Err(TypeCheckerError::Mismatch {
+            expected: expected.clone(),
+            found: expr.ty,
+            span,
+        })
+    }
+}
+
+/// Whether `from` is a pointer to a class derived from the class `to` points to.
+fn is_upcast(from: &typed_ast::Type, to: &typed_ast::Type, ctx: &TypeContext) -> bool {
+    use typed_ast::Type;
+
+    match (from, to) {
+        (Type::Ref(from), Type::Ref(to)) => match (&**from, &**to) {
+            (Type::Class(derived), Type::Class(base)) => ctx.is_subclass(derived, base),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether `from` is a pointer to a class that implements the interface `to` points to.
+fn implements(from: &typed_ast::Type, to: &typed_ast::Type, ctx: &TypeContext) -> bool {
+    use typed_ast::Type;
+
+    match (from, to) {
+        (Type::Ref(from), Type::Ref(to)) => match (&**from, &**to) {
+            (Type::Class(class), Type::Interface(interface)) => ctx
+                .class(class)
+                .is_some_and(|def| def.interfaces.contains(interface)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether `as` can convert a value of type `from` to `to`.
+fn can_cast(from: &typed_ast::Type, to: &typed_ast::Type) -> bool {
+    use typed_ast::Type;
+
+    let is_number = |ty: &Type| ty.is_integer() || *ty == Type::Char;
+    // Interface pointers can only be made by implicit conversion
+    let is_pointer = |ty: &Type| matches!(ty, Type::Ref(_) | Type::CStr) && !ty.is_interface_ref();
+    // Only these hold a whole address
+    let is_address = |ty: &Type| matches!(ty, Type::Int64 | Type::UInt64);
+
+    from == to
+        || (is_number(from) && is_number(to))
+        || (from.is_float() && (to.is_float() || to.is_integer()))
+        || (from.is_integer() && to.is_float())
+        || (*from == Type::Bool && to.is_integer())
+        || (from.is_integer() && *to == Type::Bool)
+        || (is_pointer(from) && (is_pointer(to) || is_address(to)))
+        || (is_address(from) && is_pointer(to))
+        || matches!(
+            (from, to),
+            (Type::Str, Type::CStr) | (Type::CStr, Type::Str)
+        )
+}
+
+/// Whether every path through `block` ends in a return statement.
+fn always_returns(block: &typed_ast::BlockStmt) -> bool {
+    use typed_ast::{IfOrElse, Stmt};
+
+    fn if_returns(stmt: &typed_ast::IfStmt) -> bool {
+        always_returns(&stmt.body)
+            && match &stmt.else_stmt {
+                Some(IfOrElse::If(inner)) => if_returns(inner),
+                Some(IfOrElse::Else(block)) => always_returns(block),
+                None => false,
+            }
+    }
+
+    block.inners.iter().any(|stmt| match stmt {
+        Stmt::ReturnStmt(_) => true,
+        Stmt::BlockStmt(block) => always_returns(block),
+        Stmt::IfStmt(stmt) => if_returns(stmt),
+        _ => false,
+    })
+}
+
+/// Whether a call is to the builtin `len`, which a user definition can shadow.
+fn is_builtin_len(call: &ast::FunctionCall, ctx: &TypeContext) -> bool {
+    matches!(&call.name.val, ast::ExprInner::Ident(name) if name == "len")
+        && ctx.names.get_symbol("len").is_none()
+}
+
+fn is_byte_pointer(ty: &typed_ast::Type) -> bool {
+    match ty {
+        typed_ast::Type::CStr => true,
+        typed_ast::Type::Ref(inner) => matches!(
+            **inner,
+            typed_ast::Type::Int8 | typed_ast::Type::UInt8 | typed_ast::Type::Char
+        ),
+        _ => false,
+    }
+}
+
+/// Give an expression built only from unsuffixed integer literals the type
+/// `expected`. Any other expression is returned as is.
+fn coerce_literal(
+    mut expr: typed_ast::Expr,
+    expected: &typed_ast::Type,
+) -> Result<typed_ast::Expr, TypeCheckerError> {
+    // Integer literals only become other integers, and float literals other floats
+    let same_kind = (expected.is_integer() && expr.ty.is_integer())
+        || (expected.is_float() && expr.ty.is_float());
+    if same_kind && is_untyped_literal(&expr) {
+        retype_literal(&mut expr, expected)?;
+    }
+
+    Ok(expr)
+}
+
+fn retype_literal(
+    expr: &mut typed_ast::Expr,
+    ty: &typed_ast::Type,
+) -> Result<(), TypeCheckerError> {
+    use typed_ast::{ExprInner, LiteralInner};
+
+    let span = expr.span;
+    match &mut expr.val {
+        ExprInner::Literal(lit) => {
+            match lit.val {
+                LiteralInner::Int(value) => {
+                    lit.val = int_literal_as(value, ty)
+                        .ok_or_else(|| TypeCheckerError::LiteralOutOfRange(ty.clone(), span))?;
+                }
+                LiteralInner::Double(value) if *ty == typed_ast::Type::Float => {
+                    // Too large a value would round to infinity
+                    let float = value as f32;
+                    if float.is_infinite() {
+                        return Err(TypeCheckerError::LiteralOutOfRange(ty.clone(), span));
+                    }
+                    lit.val = LiteralInner::Float(float);
+                }
+                _ => {}
+            }
+            lit.ty = ty.clone();
+        }
+        ExprInner::Binary(binary) => {
+            retype_literal(&mut binary.lhs, ty)?;
+            retype_literal(&mut binary.rhs, ty)?;
+        }
+        ExprInner::Unary(unary) => retype_literal(&mut unary.data, ty)?,
+        _ => {}
+    }
+    expr.ty = ty.clone();
+
+    Ok(())
+}
+
+fn int_literal_as(value: i128, ty: &typed_ast::Type) -> Option<typed_ast::LiteralInner> {
+    use typed_ast::{LiteralInner, Type};
+
+    Some(match ty {
+        Type::Int => LiteralInner::Int(i32::try_from(value).ok()?.into()),
+        Type::Int8 => LiteralInner::Int8(value.try_into().ok()?),
+        Type::Int16 => LiteralInner::Int16(value.try_into().ok()?),
+        Type::Int32 => LiteralInner::Int32(value.try_into().ok()?),
+        Type::Int64 => LiteralInner::Int64(value.try_into().ok()?),
+        Type::UInt => LiteralInner::UInt(value.try_into().ok()?),
+        Type::UInt8 => LiteralInner::UInt8(value.try_into().ok()?),
+        Type::UInt16 => LiteralInner::UInt16(value.try_into().ok()?),
+        Type::UInt32 => LiteralInner::UInt32(value.try_into().ok()?),
+        Type::UInt64 => LiteralInner::UInt64(value.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+/// The type of an unsuffixed integer literal before any context picks one: the
+/// first of `int`, `int64` and `uint64` that holds it.
+fn untyped_int_type(value: i128) -> typed_ast::Type {
+    if i32::try_from(value).is_ok() {
+        typed_ast::Type::Int
+    } else if i64::try_from(value).is_ok() {
+        typed_ast::Type::Int64
+    } else {
+        typed_ast::Type::UInt64
+    }
+}
+
+/// Whether `expr` is made only of unsuffixed integer or float literals, so its
+/// type can still be picked by the context it is used in.
+fn is_untyped_literal(expr: &typed_ast::Expr) -> bool {
+    use typed_ast::{BinOp, ExprInner, LiteralInner, UnaryOp};
+
+    match &expr.val {
+        ExprInner::Literal(lit) => {
+            matches!(lit.val, LiteralInner::Int(_) | LiteralInner::Double(_))
+        }
+        ExprInner::Binary(binary) => {
+            matches!(
+                binary.op,
+                BinOp::Plus
+                    | BinOp::Minus
+                    | BinOp::Times
+                    | BinOp::Divide
+                    | BinOp::Remainder
+                    | BinOp::ShiftLeft
+                    | BinOp::ShiftRight
+            ) && is_untyped_literal(&binary.lhs)
+                && is_untyped_literal(&binary.rhs)
+        }
+        ExprInner::Unary(unary) => unary.op == UnaryOp::Minus && is_untyped_literal(&unary.data),
+        _ => false,
+    }
+}
+
+/// Bring both operands of a binary expression to the same type. On failure
+/// the two operand types are returned for the error message.
+fn unify(
+    lhs: typed_ast::Expr,
+    rhs: typed_ast::Expr,
+) -> Result<(typed_ast::Expr, typed_ast::Expr), (typed_ast::Type, typed_ast::Type)> {
+    let types = (lhs.ty.clone(), rhs.ty.clone());
+
+    // Between two literals, one that fits in `int` widens to the other
+    let lhs_follows = !is_untyped_literal(&rhs) || types.0 == typed_ast::Type::Int;
+    let (lhs, rhs) = if is_untyped_literal(&lhs) && lhs_follows {
+        let lhs = coerce_literal(lhs, &types.1).map_err(|_| types.clone())?;
+        (lhs, rhs)
+    } else {
+        let rhs = coerce_literal(rhs, &types.0).map_err(|_| types.clone())?;
+        (lhs, rhs)
+    };
+
+    if lhs.ty == rhs.ty {
+        Ok((lhs, rhs))
+    } else {
+        Err(types)
+    }
+}
+
+fn function_type(
+    params: &[typed_ast::TypeBinding],
+    return_type: &typed_ast::Type,
+) -> typed_ast::Type {
+    typed_ast::Type::Function(
+        params.iter().map(|p| p.ty.clone()).collect(),
+        Box::new(return_type.clone()),
+    )
+}
+
+/// Convert a type annotation, making sure any class it names exists.
+fn check_type(
+    ty: ast::Type,
+    ctx: &mut TypeContext,
+    span: ast::Span,
+) -> Result<typed_ast::Type, TypeCheckerError> {
+    let ty = resolve_type(ty, ctx, span)?;
+    if let Some(name) = class_name(&ty) {
+        if ctx.class(name).is_none() {
+            return Err(TypeCheckerError::UnknownClass(name.to_owned(), span));
+        }
+    }
+    if let Some(name) = bare_interface(&ty) {
+        return Err(TypeCheckerError::UnsizedInterface(name.to_owned(), span));
+    }
+
+    Ok(ty)
+}
+
+fn check_types(
+    types: Vec<ast::Type>,
+    ctx: &mut TypeContext,
+    span: ast::Span,
+) -> Result<Vec<typed_ast::Type>, TypeCheckerError> {
+    types
+        .into_iter()
+        .map(|ty| check_type(ty, ctx, span))
+        .collect()
+}
+
+/// An interface used as a type other than behind a pointer.
+fn bare_interface(ty: &typed_ast::Type) -> Option<&str> {
+    use typed_ast::Type;
+
+    match ty {
+        Type::Interface(name) => Some(name),
+        Type::Ref(inner) if matches!(**inner, Type::Interface(_)) => None,
+        Type::Array(inner, _) | Type::Ref(inner) => bare_interface(inner),
+        Type::Function(params, return_type) => params
+            .iter()
+            .chain(std::iter::once(&**return_type))
+            .find_map(bare_interface),
+        _ => None,
+    }
+}
+
+/// The class at the bottom of any pointer or array layers.
+fn class_name(ty: &typed_ast::Type) -> Option<&str> {
+    match ty {
+        typed_ast::Type::Class(name) => Some(name),
+        typed_ast::Type::Array(inner, _) | typed_ast::Type::Ref(inner) => class_name(inner),
+        typed_ast::Type::Function(params, return_type) => params
+            .iter()
+            .chain(std::iter::once(&**return_type))
+            .find_map(class_name),
+        _ => None,
     }
 }
 
 impl ToTyped for ast::Literal {
     type Typed = typed_ast::Literal;
 
-    fn to_typed(self, _names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, _ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         use ast::Literal;
-        match self {
+        Ok(match self {
             Literal::Str(val) => typed_ast::Literal {
                 ty: typed_ast::Type::Str,
                 val: typed_ast::LiteralInner::Str(val),
@@ -374,7 +2565,7 @@ impl ToTyped for ast::Literal {
                 val: typed_ast::LiteralInner::Bool(val),
             },
             Literal::Int(val) => typed_ast::Literal {
-                ty: typed_ast::Type::Int,
+                ty: untyped_int_type(val),
                 val: typed_ast::LiteralInner::Int(val),
             },
             Literal::UInt(val) => typed_ast::Literal {
@@ -413,14 +2604,22 @@ impl ToTyped for ast::Literal {
                 ty: typed_ast::Type::UInt64,
                 val: typed_ast::LiteralInner::UInt64(val),
             },
-        }
+            Literal::Double(val) => typed_ast::Literal {
+                ty: typed_ast::Type::Double,
+                val: typed_ast::LiteralInner::Double(val),
+            },
+            Literal::Char(val) => typed_ast::Literal {
+                ty: typed_ast::Type::Char,
+                val: typed_ast::LiteralInner::Char(val),
+            },
+        })
     }
 }
 
 // impl ToTyped for ast::FunctionCall {
 //     type Typed = typed_ast::FunctionCall;
 //
-//     fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+//     fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
 //         todo!();
 //     }
 // }
@@ -428,13 +2627,16 @@ impl ToTyped for ast::Literal {
 impl ToTyped for ast::BinOp {
     type Typed = typed_ast::BinOp;
 
-    fn to_typed(self, _names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, _ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         use ast::BinOp;
-        match self {
+        Ok(match self {
             BinOp::Plus => typed_ast::BinOp::Plus,
             BinOp::Minus => typed_ast::BinOp::Minus,
             BinOp::Times => typed_ast::BinOp::Times,
             BinOp::Divide => typed_ast::BinOp::Divide,
+            BinOp::Remainder => typed_ast::BinOp::Remainder,
+            BinOp::ShiftLeft => typed_ast::BinOp::ShiftLeft,
+            BinOp::ShiftRight => typed_ast::BinOp::ShiftRight,
             BinOp::LogicalAnd => typed_ast::BinOp::LogicalAnd,
             BinOp::LogicalOr => typed_ast::BinOp::LogicalOr,
             BinOp::Equals => typed_ast::BinOp::Equals,
@@ -444,58 +2646,88 @@ impl ToTyped for ast::BinOp {
             BinOp::Gte => typed_ast::BinOp::Gte,
             BinOp::Lt => typed_ast::BinOp::Lt,
             BinOp::Lte => typed_ast::BinOp::Lte,
-        }
+        })
     }
 }
 
 impl ToTyped for ast::UnaryOp {
     type Typed = typed_ast::UnaryOp;
 
-    fn to_typed(self, _names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
+    fn to_typed(self, _ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
         use ast::UnaryOp;
-        match self {
+        Ok(match self {
             UnaryOp::Minus => typed_ast::UnaryOp::Minus,
             UnaryOp::Not => typed_ast::UnaryOp::Not,
             UnaryOp::Reference => typed_ast::UnaryOp::Reference,
             UnaryOp::Deref => typed_ast::UnaryOp::Deref,
-        }
+        })
     }
 }
 
 impl ToTyped for ast::TypeBinding {
     type Typed = typed_ast::TypeBinding;
 
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        typed_ast::TypeBinding {
+    fn to_typed(self, ctx: &mut TypeContext) -> Result<Self::Typed, TypeCheckerError> {
+        Ok(typed_ast::TypeBinding {
             name: self.name,
-            ty: self.ty.to_typed(names),
-        }
+            ty: check_type(self.ty, ctx, self.span)?,
+        })
     }
 }
 
-impl ToTyped for ast::Type {
-    type Typed = typed_ast::Type;
-
-    fn to_typed(self, names: &mut ScopedSymbolTable<typed_ast::Type>) -> Self::Typed {
-        use ast::Type;
-        match self {
-            Type::Class(name) => typed_ast::Type::Class(name),
-            Type::Array(inner, len) => typed_ast::Type::Array(Box::new(inner.to_typed(names)), len),
-            Type::Ref(inner) => typed_ast::Type::Ref(Box::new(inner.to_typed(names))),
-            Type::Bool => typed_ast::Type::Bool,
-            Type::Char => typed_ast::Type::Char,
-            Type::Str => typed_ast::Type::Str,
-            Type::CStr => typed_ast::Type::CStr,
-            Type::Int => typed_ast::Type::Int,
-            Type::Int8 => typed_ast::Type::Int8,
-            Type::Int16 => typed_ast::Type::Int16,
-            Type::Int32 => typed_ast::Type::Int32,
-            Type::Int64 => typed_ast::Type::Int64,
-            Type::UInt => typed_ast::Type::UInt,
-            Type::UInt8 => typed_ast::Type::UInt8,
-            Type::UInt16 => typed_ast::Type::UInt16,
-            Type::UInt32 => typed_ast::Type::UInt32,
-            Type::UInt64 => typed_ast::Type::UInt64,
+/// Convert a type, resolving type parameters and instantiating generic classes.
+fn resolve_type(
+    ty: ast::Type,
+    ctx: &mut TypeContext,
+    span: ast::Span,
+) -> Result<typed_ast::Type, TypeCheckerError> {
+    use ast::Type;
+    Ok(match ty {
+        Type::Class(name) if ctx.type_arg(&name).is_some() => ctx.type_arg(&name).unwrap().clone(),
+        Type::Class(name) if ctx.interface(&name).is_some() => typed_ast::Type::Interface(name),
+        Type::Class(name) if ctx.generic(&name).is_some() => {
+            return Err(unknown_name(&name, span, ctx))
         }
-    }
+        Type::Class(name) => typed_ast::Type::Class(name),
+        Type::Instance(name, args) => {
+            if !matches!(ctx.generic(&name), Some(Generic::Class(_))) {
+                return Err(match ctx.class(&name) {
+                    Some(_) => not_generic(&name, args.len(), span),
+                    None => TypeCheckerError::UnknownClass(name, span),
+                });
+            }
+            let args = check_types(args, ctx, span)?;
+            typed_ast::Type::Class(instantiate(&name, args, span, ctx)?)
+        }
+        Type::Array(inner, len) => {
+            typed_ast::Type::Array(Box::new(resolve_type(*inner, ctx, span)?), len)
+        }
+        Type::Ref(inner) => typed_ast::Type::Ref(Box::new(resolve_type(*inner, ctx, span)?)),
+        Type::Function(params, return_type) => typed_ast::Type::Function(
+            params
+                .into_iter()
+                .map(|p| resolve_type(p, ctx, span))
+                .collect::<Result<_, _>>()?,
+            Box::new(match return_type {
+                Some(ty) => resolve_type(*ty, ctx, span)?,
+                None => typed_ast::Type::NoneType,
+            }),
+        ),
+        Type::Bool => typed_ast::Type::Bool,
+        Type::Char => typed_ast::Type::Char,
+        Type::Str => typed_ast::Type::Str,
+        Type::CStr => typed_ast::Type::CStr,
+        Type::Int => typed_ast::Type::Int,
+        Type::Int8 => typed_ast::Type::Int8,
+        Type::Int16 => typed_ast::Type::Int16,
+        Type::Int32 => typed_ast::Type::Int32,
+        Type::Int64 => typed_ast::Type::Int64,
+        Type::UInt => typed_ast::Type::UInt,
+        Type::UInt8 => typed_ast::Type::UInt8,
+        Type::UInt16 => typed_ast::Type::UInt16,
+        Type::UInt32 => typed_ast::Type::UInt32,
+        Type::UInt64 => typed_ast::Type::UInt64,
+        Type::Float => typed_ast::Type::Float,
+        Type::Double => typed_ast::Type::Double,
+    })
 }
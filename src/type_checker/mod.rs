@@ -1,3 +1,5 @@
+pub mod const_eval;
+pub mod context;
 pub mod error;
 pub mod inference;
 pub mod typed_ast;
@@ -1,7 +1,12 @@
+use crate::ast::Span;
+
+use std::fmt;
+
 pub struct Program(pub Vec<TopLevelStmt>);
 
 pub enum TopLevelStmt {
     ClassDef(ClassDef),
+    InterfaceDef(InterfaceDef),
     FunctionDef(FunctionDef),
     ExternDef(ExternDef),
     ConstDef(GlobalConstDef),
@@ -10,7 +15,108 @@ pub enum TopLevelStmt {
 #[derive(Clone)]
 pub struct ClassDef {
     pub name: String,
-    pub fields: Vec<(String, Type)>,
+    pub base: Option<String>,
+    /// Every interface it implements, including those of its base
+    pub interfaces: Vec<String>,
+    /// Inherited fields come first, so a derived object starts with its base
+    pub fields: Vec<Field>,
+    /// Includes inherited methods that are not overridden
+    pub methods: Vec<Method>,
+    /// Objects start with a pointer to the class's vtable. Every class in a
+    /// hierarchy has one, so that upcasting never moves the fields.
+    pub has_vtable: bool,
+}
+
+impl ClassDef {
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Position of a field in the class's struct, after the vtable pointer.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        let index = self.fields.iter().position(|f| f.name == name)?;
+        Some(index + usize::from(self.has_vtable))
+    }
+
+    /// A method defined in this class's own body, rather than inherited.
+    pub fn own_method(&self, name: &str) -> Option<&Method> {
+        self.method(name).filter(|m| m.owner == self.name)
+    }
+
+    /// The virtual methods, in slot order.
+    pub fn vtable(&self) -> Vec<&Method> {
+        let mut slots: Vec<&Method> = self.methods.iter().filter(|m| m.slot.is_some()).collect();
+        slots.sort_by_key(|m| m.slot);
+        slots
+    }
+}
+
+/// Pointers to an interface are a pair of the object and a table of its
+/// class's implementations, in the order the interface lists its methods.
+#[derive(Clone)]
+pub struct InterfaceDef {
+    pub name: String,
+    /// Their slot is their index and `self` is a `*Interface`
+    pub methods: Vec<Method>,
+}
+
+impl InterfaceDef {
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+#[derive(Clone)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+    /// The class that declares it, whose methods can always use it
+    pub owner: String,
+    pub is_pub: bool,
+}
+
+/// The signature of a function in a class body. Its body is lowered like any
+/// other function, under the name from [`method_symbol`].
+#[derive(Clone)]
+pub struct Method {
+    pub name: String,
+    /// The class whose body defines it, which differs when it is inherited
+    pub owner: String,
+    pub is_pub: bool,
+    pub is_static: bool,
+    /// Index in the vtable for virtual methods
+    pub slot: Option<usize>,
+    /// Includes `self: *Owner` as the first parameter unless static
+    pub ty: Type,
+    pub span: Span,
+}
+
+/// The method `Class(args)` calls to set up a new object
+pub const INIT: &str = "init";
+/// The method called when an object is destroyed
+pub const DEINIT: &str = "deinit";
+
+/// The method `object[index]` calls when `object` is a class value
+pub const INDEX_METHOD: &str = "operator[]";
+
+/// The method a class defines to overload `op`.
+pub fn operator_method(op: BinOp) -> String {
+    format!("operator{}", op)
+}
+
+/// The name a method's function is known by, which can't clash with a user's.
+pub fn method_symbol(class: &str, method: &str) -> String {
+    format!("{}.{}", class, method)
+}
+
+/// The name of the class or function made from a generic for `args`.
+pub fn instance_name(generic: &str, args: &[Type]) -> String {
+    let args: Vec<String> = args.iter().map(Type::to_string).collect();
+    format!("{}<{}>", generic, args.join(", "))
 }
 
 // TOP LEVEL STATEMENTS
@@ -25,6 +131,7 @@ pub struct FunctionDef {
 pub struct ExternDef {
     pub name: String,
     pub params: Vec<TypeBinding>,
+    pub is_variadic: bool,
     pub return_type: Type,
 }
 
@@ -41,9 +148,12 @@ pub enum Stmt {
     BlockStmt(BlockStmt),
     IfStmt(IfStmt),
     WhileStmt(WhileStmt),
+    ForStmt(ForStmt),
     ConstDef(ConstDef),
     VarDef(VarDef),
-    ReturnStmt(Expr),
+    ReturnStmt(ReturnStmt),
+    BreakStmt(LoopJump),
+    ContinueStmt(LoopJump),
 }
 
 #[derive(Clone)]
@@ -66,10 +176,32 @@ pub enum IfOrElse {
 
 #[derive(Clone)]
 pub struct WhileStmt {
+    pub label: Option<String>,
     pub condition: Expr,
     pub body: BlockStmt,
 }
 
+#[derive(Clone)]
+pub struct ForStmt {
+    pub label: Option<String>,
+    /// The loop variable, which is a constant inside the body
+    pub binding: TypeBinding,
+    pub iter: ForIter,
+    pub body: BlockStmt,
+}
+
+#[derive(Clone)]
+pub enum ForIter {
+    /// Both ends are evaluated once, before the first iteration
+    Range(Expr, Expr),
+    Array(Expr),
+}
+
+#[derive(Clone)]
+pub struct LoopJump {
+    pub label: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct ConstDef {
     pub binding: TypeBinding,
@@ -82,25 +214,42 @@ pub struct VarDef {
     pub value: Expr,
 }
 
+#[derive(Clone)]
+pub struct ReturnStmt {
+    pub value: Expr,
+}
+
 // EXPRESSIONS
 
 #[derive(Clone)]
 pub struct Expr {
     pub ty: Type,
     pub val: ExprInner,
+    pub span: Span,
 }
 
 #[derive(Clone)]
 pub enum ExprInner {
     Class(ClassExpr),
+    /// `Class(args)`, which runs `init` on a new object
+    Construct(ConstructExpr),
     FunctionCall(FunctionCall),
     IndexExpr(IndexExpr),
+    Member(MemberExpr),
     Binary(BinaryExpr),
+    /// `self.field = value` in `init`, which stores without destroying the
+    /// field's old value, since it has none yet
+    FieldInit(BinaryExpr),
+    /// `lhs op= rhs`, which works out the address of `lhs` only once. For
+    /// objects, `rhs` is the call to the operator method on `lhs`.
+    CompoundAssign(BinaryExpr),
     Unary(UnaryExpr),
     Array(ArrayExpr),
     Cast(CastExpr),
     Literal(Literal),
     Ident(String),
+    /// The builtin `len`, for strings and arrays
+    Len(Box<Expr>),
 }
 
 #[derive(Clone)]
@@ -109,10 +258,31 @@ pub struct ClassExpr {
     pub fields: Vec<(String, Box<Expr>)>,
 }
 
+#[derive(Clone)]
+pub struct ConstructExpr {
+    pub class: String,
+    /// Not including `self`
+    pub args: Vec<Expr>,
+}
+
 #[derive(Clone)]
 pub struct FunctionCall {
     pub name: Box<Expr>,
     pub args: Vec<Expr>,
+    pub dispatch: Dispatch,
+}
+
+/// How a call finds its function.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Call the callee expression
+    Static,
+    /// Look the function up in this slot of the receiver's vtable. The
+    /// receiver is the first argument.
+    Virtual(usize),
+    /// Look the function up in this slot of the table carried by the
+    /// receiver, an interface pointer, and pass just its object as `self`
+    Interface(usize),
 }
 
 #[derive(Clone)]
@@ -121,6 +291,14 @@ pub struct IndexExpr {
     pub index: Box<Expr>,
 }
 
+/// `object.field`, where `object` is a class or a pointer to one.
+#[derive(Clone)]
+pub struct MemberExpr {
+    pub object: Box<Expr>,
+    /// Position of the field in the class's struct
+    pub index: usize,
+}
+
 #[derive(Clone)]
 pub struct BinaryExpr {
     pub lhs: Box<Expr>,
@@ -137,7 +315,6 @@ pub struct UnaryExpr {
 #[derive(Clone)]
 pub struct ArrayExpr {
     pub items: Vec<Expr>,
-    pub len: usize,
 }
 
 #[derive(Clone)]
@@ -146,12 +323,20 @@ pub struct CastExpr {
     pub to_type: Type,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Integer arithmetic wraps on overflow. `/` rounds towards zero and `%` takes
+/// the sign of the dividend. Dividing by zero stops the program with a trap;
+/// the minimum signed value divided by -1 wraps to itself, with a remainder of
+/// 0. Shift amounts are taken modulo the width of the left
+/// operand, and `>>` is arithmetic for signed types and logical for unsigned.
 pub enum BinOp {
     Plus,
     Minus,
     Times,
     Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
     LogicalAnd,
     LogicalOr,
     Equals,
@@ -163,7 +348,7 @@ pub enum BinOp {
     Lte,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UnaryOp {
     Reference,
     Deref,
@@ -181,7 +366,9 @@ pub struct Literal {
 
 #[derive(Clone)]
 pub enum LiteralInner {
-    Int(i32),
+    /// Unsuffixed, with a type of `int`, `int64` or `uint64` until the context
+    /// it is used in picks another
+    Int(i128),
     UInt(u32),
     Int8(i8),
     Int16(i16),
@@ -191,6 +378,9 @@ pub enum LiteralInner {
     UInt16(u16),
     UInt32(u32),
     UInt64(u64),
+    Float(f32),
+    Double(f64),
+    Char(u8),
     Str(String),
     CStr(String),
     Bool(bool),
@@ -202,11 +392,14 @@ pub struct TypeBinding {
     pub ty: Type,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Type {
     Class(String),
+    /// Only used behind a pointer
+    Interface(String),
     Array(Box<Type>, usize),
     Ref(Box<Type>),
+    Function(Vec<Type>, Box<Type>),
     Int,
     Int8,
     Int16,
@@ -217,9 +410,154 @@ pub enum Type {
     UInt16,
     UInt32,
     UInt64,
+    Float,
+    Double,
     Char,
     Str,
     CStr,
     Bool,
     NoneType,
 }
+
+impl Type {
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::Int
+                | Type::Int8
+                | Type::Int16
+                | Type::Int32
+                | Type::Int64
+                | Type::UInt
+                | Type::UInt8
+                | Type::UInt16
+                | Type::UInt32
+                | Type::UInt64
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::Double)
+    }
+
+    /// Whether values of this type compare and divide as unsigned numbers.
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self,
+            Type::UInt
+                | Type::UInt8
+                | Type::UInt16
+                | Type::UInt32
+                | Type::UInt64
+                | Type::Char
+                | Type::Bool
+                | Type::Ref(_)
+                | Type::CStr
+        )
+    }
+
+    /// Whether this is a pointer to an interface, which is a pair of pointers
+    /// rather than an address.
+    pub fn is_interface_ref(&self) -> bool {
+        matches!(self, Type::Ref(inner) if matches!(**inner, Type::Interface(_)))
+    }
+
+    /// Width in bits of an integer type.
+    pub fn int_bits(&self) -> Option<u32> {
+        Some(match self {
+            Type::Int8 | Type::UInt8 => 8,
+            Type::Int16 | Type::UInt16 => 16,
+            Type::Int | Type::Int32 | Type::UInt | Type::UInt32 => 32,
+            Type::Int64 | Type::UInt64 => 64,
+            _ => return None,
+        })
+    }
+
+    /// Whether values of this type have a `deinit` to run, directly or in a
+    /// field. `class` looks up the definition of a class by name.
+    pub fn needs_drop<'a>(&self, class: &impl Fn(&str) -> Option<&'a ClassDef>) -> bool {
+        match self {
+            Type::Class(name) => class(name).is_some_and(|def| {
+                def.own_method(DEINIT).is_some()
+                    || def.fields.iter().any(|f| f.ty.needs_drop(class))
+                    || def
+                        .base
+                        .as_ref()
+                        .is_some_and(|base| Type::Class(base.clone()).needs_drop(class))
+            }),
+            Type::Array(inner, _) => inner.needs_drop(class),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Class(name) | Type::Interface(name) => write!(f, "{}", name),
+            Type::Array(inner, len) => write!(f, "[{}; {}]", inner, len),
+            Type::Ref(inner) => write!(f, "*{}", inner),
+            Type::Function(params, return_type) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "func({})", params.join(", "))?;
+                if **return_type != Type::NoneType {
+                    write!(f, " -> {}", return_type)?;
+                }
+                Ok(())
+            }
+            Type::Int => write!(f, "int"),
+            Type::Int8 => write!(f, "int8"),
+            Type::Int16 => write!(f, "int16"),
+            Type::Int32 => write!(f, "int32"),
+            Type::Int64 => write!(f, "int64"),
+            Type::UInt => write!(f, "uint"),
+            Type::UInt8 => write!(f, "uint8"),
+            Type::UInt16 => write!(f, "uint16"),
+            Type::UInt32 => write!(f, "uint32"),
+            Type::UInt64 => write!(f, "uint64"),
+            Type::Float => write!(f, "float"),
+            Type::Double => write!(f, "double"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "str"),
+            Type::CStr => write!(f, "cstr"),
+            Type::Bool => write!(f, "bool"),
+            Type::NoneType => write!(f, "void"),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Times => "*",
+            BinOp::Divide => "/",
+            BinOp::Remainder => "%",
+            BinOp::ShiftLeft => "<<",
+            BinOp::ShiftRight => ">>",
+            BinOp::LogicalAnd => "and",
+            BinOp::LogicalOr => "or",
+            BinOp::Equals => "=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Gt => ">",
+            BinOp::Gte => ">=",
+            BinOp::Lt => "<",
+            BinOp::Lte => "<=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            UnaryOp::Reference => "&",
+            UnaryOp::Deref => "*",
+            UnaryOp::Minus => "-",
+            UnaryOp::Not => "not",
+        };
+        write!(f, "{}", op)
+    }
+}